@@ -0,0 +1,19 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rustc-link-lib=cups");
+
+    let bindings = bindgen::builder()
+        .header("src/bindings.h")
+        .allowlist_function("cups.*")
+        .allowlist_type("cups_.*")
+        .allowlist_var("CUPS_WHICHJOBS_.*")
+        .newtype_enum("ipp_jstate_e")
+        .newtype_enum("ipp_status_e")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()?;
+    let out_path = PathBuf::from(env::var("OUT_DIR")?);
+    bindings.write_to_file(out_path.join("bindings.rs"))?;
+    Ok(())
+}
@@ -0,0 +1 @@
+#include <cups/cups.h>
\ No newline at end of file
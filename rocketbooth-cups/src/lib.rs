@@ -0,0 +1,279 @@
+use std::{
+    ffi::{c_char, c_int, CStr, CString, NulError},
+    fmt,
+    path::Path,
+    ptr::{null, null_mut},
+    slice,
+};
+
+use sys::{
+    cupsAddOption, cupsCancelJob2, cupsFreeDests, cupsFreeJobs, cupsFreeOptions, cupsGetDest,
+    cupsGetDests2, cupsGetJobs2, cupsGetOption, cupsLastErrorString, cupsPrintFile2, cups_dest_t,
+    cups_job_t, cups_option_t, ipp_jstate_e, ipp_status_e, CUPS_WHICHJOBS_ALL,
+};
+
+mod sys;
+
+/// An error reported by CUPS, with the message from `cupsLastErrorString`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CupsError {
+    message: String,
+}
+
+impl CupsError {
+    fn last() -> Self {
+        let message = to_string(unsafe { cupsLastErrorString() });
+        if message.is_empty() {
+            Self::new("Unknown CUPS error")
+        } else {
+            Self { message }
+        }
+    }
+
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CupsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CupsError {}
+
+impl From<NulError> for CupsError {
+    fn from(_: NulError) -> Self {
+        Self::new("Unexpected NUL character in string passed to CUPS")
+    }
+}
+
+/// The state of a print queue, as `printer-state` reports it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PrinterState {
+    Idle,
+    Processing,
+    Stopped,
+}
+
+/// A print queue and what it last reported about itself.
+#[derive(Clone, Debug)]
+pub struct Printer {
+    pub name: String,
+    pub state: PrinterState,
+    /// The `printer-state-reasons` keywords, eg `media-empty-error`, without `none`.
+    pub reasons: Vec<String>,
+    /// The `printer-state-message`, often the driver's description of the problem.
+    pub message: String,
+    pub accepting_jobs: bool,
+}
+
+/// The state of a print job, as `job-state` reports it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Held,
+    Processing,
+    Stopped,
+    Canceled,
+    Aborted,
+    Completed,
+}
+
+impl JobState {
+    /// Whether the job has left the queue, one way or another.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Canceled | Self::Aborted | Self::Completed)
+    }
+
+    fn from_ipp(state: ipp_jstate_e) -> Self {
+        match state {
+            ipp_jstate_e::IPP_JSTATE_PENDING => Self::Pending,
+            ipp_jstate_e::IPP_JSTATE_HELD => Self::Held,
+            ipp_jstate_e::IPP_JSTATE_PROCESSING => Self::Processing,
+            ipp_jstate_e::IPP_JSTATE_STOPPED => Self::Stopped,
+            ipp_jstate_e::IPP_JSTATE_CANCELED => Self::Canceled,
+            ipp_jstate_e::IPP_JSTATE_ABORTED => Self::Aborted,
+            _ => Self::Completed,
+        }
+    }
+}
+
+/// The destinations known to CUPS, freed on drop.
+struct Dests {
+    count: c_int,
+    dests: *mut cups_dest_t,
+}
+
+impl Dests {
+    fn get() -> Self {
+        let mut dests = null_mut();
+        let count = unsafe { cupsGetDests2(null_mut(), &mut dests) };
+        Self { count, dests }
+    }
+
+    /// The named destination, or the default one.
+    fn find(&self, name: Option<&str>) -> Result<&cups_dest_t, CupsError> {
+        let name = name.map(CString::new).transpose()?;
+        let dest = unsafe {
+            cupsGetDest(
+                name.as_ref().map_or(null(), |name| name.as_ptr()),
+                null(),
+                self.count,
+                self.dests,
+            )
+        };
+        unsafe { dest.as_ref() }.ok_or_else(|| match name {
+            Some(name) => CupsError::new(format!("No printer named {}", name.to_string_lossy())),
+            None => CupsError::new("No default printer"),
+        })
+    }
+}
+
+impl Drop for Dests {
+    fn drop(&mut self) {
+        unsafe { cupsFreeDests(self.count, self.dests) }
+    }
+}
+
+/// Looks up a print queue, or the default one if `name` is `None`.
+pub fn printer(name: Option<&str>) -> Result<Printer, CupsError> {
+    let dests = Dests::get();
+    let dest = dests.find(name)?;
+    let option = |option: &CStr| {
+        to_string(unsafe { cupsGetOption(option.as_ptr(), dest.num_options, dest.options) })
+    };
+    let state = match option(c"printer-state").as_str() {
+        "4" => PrinterState::Processing,
+        "5" => PrinterState::Stopped,
+        _ => PrinterState::Idle,
+    };
+    let reasons = option(c"printer-state-reasons")
+        .split(',')
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty() && *reason != "none")
+        .map(String::from)
+        .collect();
+    Ok(Printer {
+        name: to_string(dest.name),
+        state,
+        reasons,
+        message: option(c"printer-state-message"),
+        accepting_jobs: option(c"printer-is-accepting-jobs") != "false",
+    })
+}
+
+/// Options for a print job, eg `media` or `print-scaling`, freed on drop.
+struct Options {
+    count: c_int,
+    options: *mut cups_option_t,
+}
+
+impl Options {
+    fn new<'a>(options: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, CupsError> {
+        let mut result = Self {
+            count: 0,
+            options: null_mut(),
+        };
+        for (name, value) in options {
+            let name = CString::new(name)?;
+            let value = CString::new(value)?;
+            result.count = unsafe {
+                cupsAddOption(
+                    name.as_ptr(),
+                    value.as_ptr(),
+                    result.count,
+                    &mut result.options,
+                )
+            };
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for Options {
+    fn drop(&mut self) {
+        unsafe { cupsFreeOptions(self.count, self.options) }
+    }
+}
+
+/// Sends a file to the named print queue, returning the new job's ID.
+pub fn print_file<'a>(
+    printer: &str,
+    path: &Path,
+    title: &str,
+    options: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<c_int, CupsError> {
+    let printer = CString::new(printer)?;
+    let path = CString::new(path.to_string_lossy().as_bytes())?;
+    let title = CString::new(title)?;
+    let options = Options::new(options)?;
+    let job_id = unsafe {
+        cupsPrintFile2(
+            null_mut(),
+            printer.as_ptr(),
+            path.as_ptr(),
+            title.as_ptr(),
+            options.count,
+            options.options,
+        )
+    };
+    if job_id == 0 {
+        Err(CupsError::last())
+    } else {
+        Ok(job_id)
+    }
+}
+
+/// The state of a job on the named queue, or `None` if CUPS no longer knows about it.
+pub fn job_state(printer: &str, job_id: c_int) -> Result<Option<JobState>, CupsError> {
+    let printer = CString::new(printer)?;
+    let mut jobs: *mut cups_job_t = null_mut();
+    let count = unsafe {
+        cupsGetJobs2(
+            null_mut(),
+            &mut jobs,
+            printer.as_ptr(),
+            0,
+            CUPS_WHICHJOBS_ALL,
+        )
+    };
+    if count < 0 {
+        return Err(CupsError::last());
+    }
+    let state = if jobs.is_null() {
+        None
+    } else {
+        unsafe { slice::from_raw_parts(jobs, count as usize) }
+            .iter()
+            .find(|job| job.id == job_id)
+            .map(|job| JobState::from_ipp(job.state))
+    };
+    unsafe { cupsFreeJobs(count, jobs) };
+    Ok(state)
+}
+
+/// Cancels a job on the named queue.
+pub fn cancel_job(printer: &str, job_id: c_int) -> Result<(), CupsError> {
+    let printer = CString::new(printer)?;
+    let status = unsafe { cupsCancelJob2(null_mut(), printer.as_ptr(), job_id, 0) };
+    if status.0 >= ipp_status_e::IPP_STATUS_ERROR_BAD_REQUEST.0 {
+        Err(CupsError::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// Copies a string owned by CUPS, treating null as empty.
+fn to_string(value: *const c_char) -> String {
+    if value.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(value) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
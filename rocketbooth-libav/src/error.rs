@@ -0,0 +1,90 @@
+use std::{
+    ffi::{c_int, CStr, NulError},
+    fmt,
+};
+
+use libc::{EAGAIN, EINVAL, ENOMEM};
+
+use crate::sys::{av_strerror, AV_ERROR_MAX_STRING_SIZE};
+
+/// Equivalent of libav's `FFERRTAG` macro, which bindgen can't translate.
+const fn fferrtag(a: u8, b: u8, c: u8, d: u8) -> c_int {
+    -((a as c_int) | (b as c_int) << 8 | (c as c_int) << 16 | (d as c_int) << 24)
+}
+
+pub const AVERROR_EOF: c_int = fferrtag(b'E', b'O', b'F', b' ');
+pub const AVERROR_EXIT: c_int = fferrtag(b'E', b'X', b'I', b'T');
+pub const AVERROR_INVALIDDATA: c_int = fferrtag(b'I', b'N', b'D', b'A');
+pub const AVERROR_DECODER_NOT_FOUND: c_int = fferrtag(0xF8, b'D', b'E', b'C');
+pub const AVERROR_DEMUXER_NOT_FOUND: c_int = fferrtag(0xF8, b'D', b'E', b'M');
+
+/// An error reported by libav, or by these wrappers in libav's terms.
+///
+/// Carries the (negative) AVERROR code along with the message `av_strerror` produces for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvError {
+    code: c_int,
+    message: String,
+}
+
+impl AvError {
+    pub fn from_code(code: c_int) -> Self {
+        let mut buf = [0; AV_ERROR_MAX_STRING_SIZE as usize];
+        let status = unsafe { av_strerror(code, buf.as_mut_ptr(), buf.len()) };
+        let message = if status == 0 {
+            unsafe { CStr::from_ptr(buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            format!("Unknown error {code}")
+        };
+        Self { code, message }
+    }
+
+    pub fn from_errno(errno: c_int) -> Self {
+        Self::from_code(-errno)
+    }
+
+    pub fn out_of_memory() -> Self {
+        Self::from_errno(ENOMEM)
+    }
+
+    /// Returns `Ok(status)` for non-negative status codes, and the corresponding error otherwise.
+    pub fn check(status: c_int) -> Result<c_int, Self> {
+        if status < 0 {
+            Err(Self::from_code(status))
+        } else {
+            Ok(status)
+        }
+    }
+
+    pub fn code(&self) -> c_int {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.code == AVERROR_EOF
+    }
+
+    pub fn is_again(&self) -> bool {
+        self.code == -EAGAIN
+    }
+}
+
+impl fmt::Display for AvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (AVERROR {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for AvError {}
+
+impl From<NulError> for AvError {
+    fn from(_: NulError) -> Self {
+        Self::from_errno(EINVAL)
+    }
+}
@@ -1,34 +1,40 @@
 use core::slice;
 use std::{
-    ffi::{CStr, CString, NulError},
+    cell::Cell,
+    ffi::{c_int, c_void, CStr, CString},
     ptr::null_mut,
+    time::{Duration, Instant},
 };
 
-use libc::{EAGAIN, EINVAL};
+use libc::{EAGAIN, EINVAL, ETIMEDOUT};
 use sys::{
     av_dict_free, av_dict_set, av_find_input_format, av_frame_alloc, av_frame_free,
     av_frame_get_buffer, av_free, av_get_padded_bits_per_pixel, av_malloc, av_packet_alloc,
     av_packet_free, av_packet_unref, av_pix_fmt_desc_get, av_read_frame, avcodec_alloc_context3,
     avcodec_find_decoder, avcodec_find_decoder_by_name, avcodec_free_context, avcodec_open2,
     avcodec_parameters_to_context, avcodec_receive_frame, avcodec_send_packet,
-    avdevice_register_all, avformat_close_input, avformat_find_stream_info, avformat_open_input,
-    sws_freeContext, sws_getContext, sws_scale, AVCodecContext, AVDictionary, AVFormatContext,
-    AVFrame, AVInputFormat, AVMediaType, AVPacket, AVPixelFormat, AVStream, SwsContext,
-    AV_PIX_FMT_FLAG_RGB, SWS_FAST_BILINEAR,
+    avdevice_register_all, avformat_alloc_context, avformat_close_input, avformat_find_stream_info,
+    avformat_open_input, sws_freeContext, sws_getContext, sws_scale, AVCodecContext, AVDictionary,
+    AVFormatContext, AVFrame, AVIOInterruptCB, AVInputFormat, AVMediaType, AVPacket, AVPixelFormat,
+    AVStream, SwsContext, AV_PIX_FMT_FLAG_RGB, SWS_FAST_BILINEAR,
 };
 
+pub use error::{
+    AvError, AVERROR_DECODER_NOT_FOUND, AVERROR_DEMUXER_NOT_FOUND, AVERROR_EOF, AVERROR_EXIT,
+    AVERROR_INVALIDDATA,
+};
+
+mod error;
 mod sys;
 pub struct Dictionary(*mut AVDictionary);
 
 unsafe impl Send for Dictionary {}
 
 impl Dictionary {
-    pub fn set(&mut self, key: &str, value: &str) -> Result<(), NulError> {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), AvError> {
         let key = CString::new(key)?;
         let value = CString::new(value)?;
-        unsafe {
-            av_dict_set(&mut self.0, key.as_ptr(), value.as_ptr(), 0);
-        }
+        AvError::check(unsafe { av_dict_set(&mut self.0, key.as_ptr(), value.as_ptr(), 0) })?;
         Ok(())
     }
 }
@@ -77,10 +83,11 @@ impl Dictionary {
 pub struct Packet(*mut AVPacket);
 
 impl Packet {
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self, AvError> {
         Some(unsafe { av_packet_alloc() })
             .filter(|ptr| !ptr.is_null())
             .map(Packet)
+            .ok_or_else(AvError::out_of_memory)
     }
 
     pub fn stream_index(&self) -> i32 {
@@ -101,10 +108,11 @@ impl Drop for Packet {
 pub struct Buffer(*mut std::os::raw::c_void);
 
 impl Buffer {
-    pub fn new(size: usize) -> Option<Self> {
+    pub fn new(size: usize) -> Result<Self, AvError> {
         Some(unsafe { av_malloc(size) })
             .filter(|ptr| !ptr.is_null())
             .map(Self)
+            .ok_or_else(AvError::out_of_memory)
     }
 }
 
@@ -121,60 +129,140 @@ pub struct Format(*mut AVInputFormat);
 unsafe impl Send for Format {}
 
 impl Format {
-    pub fn from_name(name: &str) -> Option<Self> {
+    pub fn from_name(name: &str) -> Result<Self, AvError> {
         FORMAT_INIT.call_once(|| unsafe {
             avdevice_register_all();
         });
-        let name = CString::new(name).ok()?;
+        let name = CString::new(name)?;
         Some(unsafe { av_find_input_format(name.as_ptr()) })
             .filter(|ptr| !ptr.is_null())
             .map(|ptr| Self(ptr as _))
+            .ok_or_else(|| AvError::from_code(AVERROR_DEMUXER_NOT_FOUND))
     }
 }
 
-pub struct FormatContext(*mut AVFormatContext);
+/// Limits on how much work opening an input may take, mirroring ffmpeg's `-probesize` and
+/// `-analyzeduration` options plus an overall timeout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProbeLimits {
+    /// Maximum number of bytes to read while probing the input.
+    pub probesize: Option<i64>,
+    /// Maximum duration of input to analyze while looking for stream information.
+    pub analyze_duration: Option<Duration>,
+    /// Limit on the time spent in `FormatContext::open_with_limits` and `find_stream_info`
+    /// combined. Blocking calls are interrupted once it elapses.
+    pub timeout: Option<Duration>,
+}
+
+/// Shared with libav through the interrupt callback's opaque pointer.
+type Deadline = Cell<Option<Instant>>;
+
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    let deadline = unsafe { &*(opaque as *const Deadline) };
+    deadline
+        .get()
+        .is_some_and(|deadline| Instant::now() >= deadline) as c_int
+}
+
+/// Reports interruptions caused by a passed deadline as timeouts rather than libav's generic
+/// "Immediate exit requested".
+fn check_deadline(status: c_int, deadline: &Deadline) -> Result<c_int, AvError> {
+    AvError::check(status).map_err(|e| {
+        let expired = deadline
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if e.code() == AVERROR_EXIT && expired {
+            AvError::from_errno(ETIMEDOUT)
+        } else {
+            e
+        }
+    })
+}
+
+pub struct FormatContext {
+    context: *mut AVFormatContext,
+    deadline: Box<Deadline>,
+}
 
 impl FormatContext {
     pub fn open(
+        path: &str,
+        format: Option<Format>,
+        options: Option<Dictionary>,
+    ) -> Result<FormatContext, AvError> {
+        Self::open_with_limits(path, format, options, &ProbeLimits::default())
+    }
+
+    pub fn open_with_limits(
         path: &str,
         format: Option<Format>,
         mut options: Option<Dictionary>,
-    ) -> Option<FormatContext> {
-        let mut context = null_mut();
-        let path = CString::new(path).ok()?;
+        limits: &ProbeLimits,
+    ) -> Result<FormatContext, AvError> {
+        let path = CString::new(path)?;
+        let mut context = Some(unsafe { avformat_alloc_context() })
+            .filter(|ptr| !ptr.is_null())
+            .ok_or_else(AvError::out_of_memory)?;
+        let deadline = Box::new(Deadline::new(
+            limits.timeout.map(|timeout| Instant::now() + timeout),
+        ));
+        unsafe {
+            (*context).interrupt_callback = AVIOInterruptCB {
+                callback: Some(interrupt_callback),
+                opaque: &*deadline as *const Deadline as *mut c_void,
+            };
+            if let Some(probesize) = limits.probesize {
+                (*context).probesize = probesize;
+            }
+            if let Some(analyze_duration) = limits.analyze_duration {
+                (*context).max_analyze_duration = analyze_duration.as_micros() as i64;
+            }
+        }
         let format = format.map_or(null_mut(), |fmt| fmt.0);
         let mut fallback_options = null_mut();
         let options = options
             .as_mut()
             .map_or(&mut fallback_options, |dict| &mut dict.0);
+        // On failure libav frees the context we allocated.
         let status = unsafe { avformat_open_input(&mut context, path.as_ptr(), format, options) };
-        if status == 0 {
-            Some(FormatContext(context))
-        } else {
-            None
-        }
+        check_deadline(status, &deadline)?;
+        Ok(FormatContext { context, deadline })
     }
 
-    pub fn find_stream_info(&mut self) {
-        let status = unsafe { avformat_find_stream_info(self.0, null_mut()) };
-        assert!(status >= 0);
+    /// Replaces the deadline set by `ProbeLimits::timeout`; reads are interrupted once it passes.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.deadline
+            .set(timeout.map(|timeout| Instant::now() + timeout));
+    }
+
+    pub fn find_stream_info(&mut self) -> Result<(), AvError> {
+        let status = unsafe { avformat_find_stream_info(self.context, null_mut()) };
+        check_deadline(status, &self.deadline)?;
+        Ok(())
     }
 
     pub fn streams(&mut self) -> impl Iterator<Item = Stream> + '_ {
-        let as_slice =
-            unsafe { std::slice::from_raw_parts((*self.0).streams, (*self.0).nb_streams as usize) };
+        let as_slice = unsafe {
+            std::slice::from_raw_parts((*self.context).streams, (*self.context).nb_streams as usize)
+        };
         as_slice.iter().map(|&av_stream| Stream(av_stream))
     }
 
-    pub fn read_into(&mut self, packet: &mut Packet) -> bool {
-        0 == unsafe { av_read_frame(self.0, packet.0) }
+    /// Reads the next packet, returning `Ok(false)` once the end of the input is reached.
+    pub fn read_into(&mut self, packet: &mut Packet) -> Result<bool, AvError> {
+        let status = unsafe { av_read_frame(self.context, packet.0) };
+        match check_deadline(status, &self.deadline) {
+            Ok(_) => Ok(true),
+            Err(e) if e.is_eof() => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 }
 
 impl Drop for FormatContext {
     fn drop(&mut self) {
         unsafe {
-            avformat_close_input(&mut self.0);
+            avformat_close_input(&mut self.context);
         }
     }
 }
@@ -184,33 +272,29 @@ pub struct Frame(*mut AVFrame);
 unsafe impl Send for Frame {}
 
 impl Frame {
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self, AvError> {
         Some(unsafe { av_frame_alloc() })
             .filter(|ptr| !ptr.is_null())
             .map(Self)
+            .ok_or_else(AvError::out_of_memory)
     }
 
-    fn alloc(width: i32, height: i32, format: AVPixelFormat) -> Option<Self> {
-        let ptr = unsafe { av_frame_alloc() };
-        if ptr.is_null() {
-            return None;
-        }
+    fn alloc(width: i32, height: i32, format: AVPixelFormat) -> Result<Self, AvError> {
+        let frame = Self::new()?;
         unsafe {
-            (*ptr).width = width;
-            (*ptr).height = height;
-            (*ptr).format = format.0;
+            (*frame.0).width = width;
+            (*frame.0).height = height;
+            (*frame.0).format = format.0;
         }
-        if unsafe { av_frame_get_buffer(ptr, 0) } != 0 {
-            return None;
-        };
-        Some(Self(ptr))
+        AvError::check(unsafe { av_frame_get_buffer(frame.0, 0) })?;
+        Ok(frame)
     }
 
-    pub fn alloc_rgb24(width: i32, height: i32) -> Option<Self> {
+    pub fn alloc_rgb24(width: i32, height: i32) -> Result<Self, AvError> {
         Self::alloc(width, height, AVPixelFormat::AV_PIX_FMT_RGB24)
     }
 
-    pub fn alloc_yuv420p(width: i32, height: i32) -> Option<Self> {
+    pub fn alloc_yuv420p(width: i32, height: i32) -> Result<Self, AvError> {
         Self::alloc(width, height, AVPixelFormat::AV_PIX_FMT_YUV420P)
     }
 
@@ -313,33 +397,30 @@ impl Stream {
         codec_type == AVMediaType::AVMEDIA_TYPE_VIDEO
     }
 
-    pub fn create_decoder(&self, name: Option<&str>) -> Option<Decoder> {
+    pub fn create_decoder(&self, name: Option<&str>) -> Result<Decoder, AvError> {
         let borrowed_codec = unsafe { (*self.0).codecpar };
         let codec = if let Some(name) = name {
-            unsafe {
-                let name = CString::new(name).unwrap();
-                avcodec_find_decoder_by_name(name.as_ptr())
-            }
+            let name = CString::new(name)?;
+            unsafe { avcodec_find_decoder_by_name(name.as_ptr()) }
         } else {
             unsafe { avcodec_find_decoder((*borrowed_codec).codec_id) }
         };
         if codec.is_null() {
-            return None;
+            return Err(AvError::from_code(AVERROR_DECODER_NOT_FOUND));
         }
-        let decoder = unsafe { avcodec_alloc_context3(codec) };
-        let status = unsafe { avcodec_parameters_to_context(decoder, borrowed_codec) };
-        if status != 0 {
-            return None;
-        }
-        unsafe { avcodec_open2(decoder, codec, null_mut()) };
-        Some(Decoder { decoder })
+        let decoder = Some(unsafe { avcodec_alloc_context3(codec) })
+            .filter(|ptr| !ptr.is_null())
+            .map(|decoder| Decoder { decoder })
+            .ok_or_else(AvError::out_of_memory)?;
+        AvError::check(unsafe { avcodec_parameters_to_context(decoder.decoder, borrowed_codec) })?;
+        AvError::check(unsafe { avcodec_open2(decoder.decoder, codec, null_mut()) })?;
+        Ok(decoder)
     }
 }
 
 #[derive(Debug)]
 pub enum ReceiveResult {
     Success,
-    Error,
     Pending,
     Done,
 }
@@ -349,21 +430,23 @@ pub struct Decoder {
 }
 
 impl Decoder {
-    pub fn send(&mut self, packet: &mut Packet) -> bool {
-        0 == unsafe { -avcodec_send_packet(self.decoder, packet.0) }
+    pub fn send(&mut self, packet: &mut Packet) -> Result<(), AvError> {
+        AvError::check(unsafe { avcodec_send_packet(self.decoder, packet.0) })?;
+        Ok(())
     }
 
-    pub fn send_eof(&mut self) -> bool {
-        0 == unsafe { avcodec_send_packet(self.decoder, null_mut()) }
+    pub fn send_eof(&mut self) -> Result<(), AvError> {
+        AvError::check(unsafe { avcodec_send_packet(self.decoder, null_mut()) })?;
+        Ok(())
     }
 
-    pub fn receive(&mut self, frame: &mut Frame) -> ReceiveResult {
+    pub fn receive(&mut self, frame: &mut Frame) -> Result<ReceiveResult, AvError> {
         let status = unsafe { avcodec_receive_frame(self.decoder, frame.0) };
         match -status {
-            0 => ReceiveResult::Success,
-            EAGAIN => ReceiveResult::Pending,
-            EINVAL => ReceiveResult::Error,
-            _ => ReceiveResult::Done,
+            0 => Ok(ReceiveResult::Success),
+            EAGAIN => Ok(ReceiveResult::Pending),
+            _ if status == AVERROR_EOF => Ok(ReceiveResult::Done),
+            _ => Err(AvError::from_code(status)),
         }
     }
 }
@@ -386,7 +469,7 @@ impl ScalingContext {
         dst_width: i32,
         dst_height: i32,
         dst_format: AVPixelFormat,
-    ) -> Self {
+    ) -> Result<Self, AvError> {
         let context = unsafe {
             sws_getContext(
                 src_width,
@@ -401,11 +484,15 @@ impl ScalingContext {
                 null_mut(),
             )
         };
-        ScalingContext(context)
+        if context.is_null() {
+            // swscale doesn't report why; in practice it's an unsupported format or size.
+            return Err(AvError::from_errno(EINVAL));
+        }
+        Ok(ScalingContext(context))
     }
 
-    pub fn scale(&mut self, src: &Frame, dest: &mut Frame) {
-        unsafe {
+    pub fn scale(&mut self, src: &Frame, dest: &mut Frame) -> Result<(), AvError> {
+        let status = unsafe {
             let src_slice = (*src.0).data.as_ptr().cast::<*const u8>();
             let src_stride = (*src.0).linesize.as_ptr();
             let dst_slice = (*dest.0).data.as_ptr();
@@ -418,8 +505,10 @@ impl ScalingContext {
                 src.height() as _,
                 dst_slice,
                 dst_stride,
-            );
-        }
+            )
+        };
+        AvError::check(status)?;
+        Ok(())
     }
 }
 
@@ -0,0 +1,23 @@
+//! Takes a single photo with the configured capture command, eg to check a gphoto2 setup.
+//!
+//! A shell script can stand in for the camera:
+//! `command = ["sh", "-c", "cp test.jpg \"$0\""]`
+use rocketbooth::{run_capture_command, Config};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let config_path = args.next().unwrap_or_else(|| "Rocketbooth.toml".into());
+    let output_path = args.next().unwrap_or_else(|| "capture.jpg".into());
+    let config: Config = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+    let capture_command = config
+        .capture_command
+        .ok_or("No capture_command configured")?;
+    let image = run_capture_command(&capture_command)?;
+    image.save(&output_path)?;
+    println!(
+        "Saved {}x{} photo to {output_path}",
+        image.width(),
+        image.height()
+    );
+    Ok(())
+}
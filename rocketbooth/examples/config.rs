@@ -1,4 +1,7 @@
-use rocketbooth::{Config, Controls, ImageLayout, ImageSettings, VideoSource};
+use rocketbooth::{
+    Config, Controls, EventInfo, Framing, ImageLayout, ImageSettings, Orientation, Prompts, Timing,
+    VideoSource,
+};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config {
@@ -7,16 +10,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             video_codec: None,
             display_size: None,
             format: None,
+            probesize: None,
+            analyzeduration: None,
+            open_timeout: 10.0,
+            read_timeout: 5.0,
+            keep_warm: 300.0,
             options: Default::default(),
         },
+        capture_source: None,
+        capture_command: None,
+        printer: None,
+        review: None,
+        copies: None,
         image: Some(ImageSettings {
             prefix: None,
             format: None,
             layout: ImageLayout::default(),
+            framing: Framing::default(),
+            template: None,
             enable_post_command: true,
             post_command: Some(vec!["echo".into(), "hi".into()]),
         }),
         controls: Controls::default(),
+        gpio: vec![],
+        gpio_outputs: vec![],
+        serial: None,
+        control: None,
+        timing: Timing::default(),
+        theme: None,
+        prompts: Prompts::default(),
+        event: EventInfo::default(),
+        orientation: Orientation::default(),
     };
     let serialized = &toml::to_string(&config)?;
     println!("{serialized}");
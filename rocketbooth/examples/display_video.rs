@@ -69,29 +69,26 @@ fn read_video_frames(sender: SyncSender<Frame>) -> Result<(), Box<dyn std::error
         "/mnt/c/Users/cdwin/Downloads/VID_20171212_211842.mp4",
         None,
         None,
-    )
-    .ok_or("Failed to open file")?;
-    context.find_stream_info();
+    )?;
+    context.find_stream_info()?;
     let video_stream = context
         .streams()
         .find(|stream| stream.is_video())
         .ok_or("No video stream")?;
-    let mut decoder = video_stream
-        .create_decoder(None)
-        .ok_or("Codec failed to initialize")?;
-    let mut packet = Packet::new().ok_or("Could not allocate packet")?;
-    'read: while context.read_into(&mut packet) {
+    let mut decoder = video_stream.create_decoder(None)?;
+    let mut packet = Packet::new()?;
+    'read: while context.read_into(&mut packet)? {
         if packet.stream_index() == video_stream.index() {
-            decoder.send(&mut packet);
-            let mut frame = Frame::new().ok_or("Failed to initialize frame")?;
+            decoder.send(&mut packet)?;
+            let mut frame = Frame::new()?;
             'receive: loop {
-                let result = decoder.receive(&mut frame);
+                let result = decoder.receive(&mut frame)?;
                 match result {
                     ReceiveResult::Done => break 'read,
-                    ReceiveResult::Pending | ReceiveResult::Error => break 'receive,
+                    ReceiveResult::Pending => break 'receive,
                     ReceiveResult::Success => {
                         sender.send(frame)?;
-                        frame = Frame::new().ok_or("Failed to reinitialize frame")?;
+                        frame = Frame::new()?;
                     }
                 }
             }
@@ -0,0 +1,35 @@
+//! Prints what the configured [[gpio]] buttons do as they're pressed, eg to check the wiring and
+//! their timings: `cargo run -F gpio --example gpio -- Rocketbooth.toml`. Stop it with ctrl+c.
+use rocketbooth::{Config, GpioInput};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "Rocketbooth.toml".into());
+    let config: Config = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+    config.validate()?;
+    let inputs = config.gpio_inputs();
+    if inputs.is_empty() {
+        return Err("No [[gpio]] buttons configured".into());
+    }
+    read(inputs)
+}
+
+#[cfg(feature = "gpio")]
+fn read(inputs: Vec<GpioInput>) -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+
+    let mut chip = rocketbooth::ChipGpio::default();
+    let mut buttons = rocketbooth::GpioButtons::open(&mut chip, inputs)?;
+    let start = Instant::now();
+    loop {
+        for action in buttons.read(&mut chip, Duration::from_millis(50))? {
+            println!("{:>6.2}s {action:?}", start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+fn read(_inputs: Vec<GpioInput>) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Reading the pins needs the gpio feature".into())
+}
@@ -0,0 +1,40 @@
+//! Prints an image with the configured [printer] settings and follows the job, eg to check a
+//! new printer, or the error screens against a stand-in queue.
+//!
+//! Needs the cups feature: `cargo run --features cups --example print -- Rocketbooth.toml a.jpg`,
+//! optionally followed by the number of copies.
+use std::{path::PathBuf, time::Duration};
+
+use rocketbooth::{print_and_follow, Config, PrintJob, PrintStatus};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let config_path = args.next().unwrap_or_else(|| "Rocketbooth.toml".into());
+    let image_path = PathBuf::from(args.next().ok_or("No image to print")?);
+    let copies = args.next().map_or(Ok(1), |copies| copies.parse())?;
+    let config: Config = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+    let printer = config.printer.ok_or("No [printer] configured")?;
+    printer.validate()?;
+
+    let print_job = PrintJob::default();
+    let follower = {
+        let print_job = print_job.clone();
+        std::thread::spawn(move || print_and_follow(&printer, &image_path, copies, &print_job))
+    };
+    let mut last = None;
+    loop {
+        let report = (print_job.status(), print_job.problems());
+        if last.as_ref() != Some(&report) {
+            println!("{:?} {:?}", report.0, report.1);
+            last = Some(report);
+        }
+        if follower.is_finished() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    match print_job.status() {
+        PrintStatus::Failed(e) => Err(e.into()),
+        _ => Ok(()),
+    }
+}
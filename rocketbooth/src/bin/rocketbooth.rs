@@ -1,11 +1,20 @@
 use std::env::args;
 
-use rocketbooth::{ContextBuilder, State};
+use rocketbooth::{
+    Config, Context, ContextBuilder, ControlCommand, ControlRequest, InputEvent, State,
+};
+use sdl2::{
+    render::{Canvas, Texture},
+    video::Window,
+};
 
-#[cfg(feature = "gpio")]
-struct GpioEvent();
+const CTL_USAGE: &str = "Usage: rocketbooth ctl [-c CONFIG] \
+    status|trigger|pause|resume|reprint [COPIES]|reload|shutdown";
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if args().nth(1).as_deref() == Some("ctl") {
+        return ctl(args().skip(2).collect());
+    }
     let candidate_config_paths: Vec<String> = args().skip(1).collect();
     let candidate_config_paths = if candidate_config_paths.is_empty() {
         vec![String::from("Rocketbooth.toml")]
@@ -18,35 +27,62 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         .ok_or_else(|| format!("No valid config file found; checked {candidate_config_paths:?}"))?;
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
+    // Inputs besides the keyboard and screen send their events through SDL's event queue.
+    let event_subsystem = sdl_context.event()?;
+    event_subsystem.register_custom_event::<InputEvent>()?;
+    event_subsystem.register_custom_event::<ControlRequest>()?;
+    // The booth runs without the control socket, rather than not at all, if it can't be made.
+    let socket_paths = (context_builder.config().control.as_ref())
+        .map(|control| control.socket_paths(context_builder.path()));
+    match socket_paths.map(|paths| rocketbooth::bind_control_socket(&paths)) {
+        Some(Ok((listener, _))) => {
+            let sender = event_subsystem.event_sender();
+            std::thread::spawn(move || {
+                let send = move |request| Ok(sender.push_custom_event(request)?);
+                if let Err(e) = rocketbooth::serve_control(listener, send) {
+                    eprintln!("Control socket stopped: {e}");
+                }
+            });
+        }
+        Some(Err(e)) => eprintln!("Control socket unavailable: {e}"),
+        None => {}
+    }
     #[cfg(feature = "gpio")]
     let _gpio_worker = {
-        let ev = sdl_context.event().unwrap();
-        ev.register_custom_event::<GpioEvent>().unwrap();
-        let sender = ev.event_sender();
-        std::thread::spawn(move || {
-            use gpiochip;
-            let chip = gpiochip::GpioChip::new("/dev/gpiochip0").unwrap();
-            let button = chip
-                .request_event(
-                    "rocketbooth",
-                    2,
-                    gpiochip::RequestFlags::INPUT,
-                    gpiochip::EventRequestFlags::RISING_EDGE,
-                )
-                .unwrap();
-            let mut last_fired_event = 0u64;
-            loop {
-                let bitmap = gpiochip::wait_for_event(&[&button], 200).unwrap();
-                if bitmap & 0b01 == 0b01 {
-                    let event = button.read().unwrap();
-                    if event.timestamp > last_fired_event + 500_000_000u64 {
-                        last_fired_event = event.timestamp;
-                        sender.push_custom_event(GpioEvent()).unwrap();
+        let inputs = context_builder.config().gpio_inputs();
+        let sender = event_subsystem.event_sender();
+        (!inputs.is_empty()).then(|| {
+            std::thread::spawn(move || {
+                let read_buttons = || -> Result<(), Box<dyn std::error::Error>> {
+                    let mut chip = rocketbooth::ChipGpio::default();
+                    let mut buttons = rocketbooth::GpioButtons::open(&mut chip, inputs)?;
+                    loop {
+                        let timeout = std::time::Duration::from_millis(50);
+                        for action in buttons.read(&mut chip, timeout)? {
+                            sender.push_custom_event(InputEvent::Action(action))?;
+                        }
                     }
+                };
+                if let Err(e) = read_buttons() {
+                    eprintln!("GPIO buttons stopped: {e}");
                 }
-            }
+            })
         })
     };
+    // The serial controller's commands are read on their own thread, which keeps it connected,
+    // and the booth's state is written back to it from the event loop.
+    let serial = context_builder.config().serial.clone().map(|settings| {
+        let port = rocketbooth::SerialPort::default();
+        let reader_port = port.clone();
+        let sender = event_subsystem.event_sender();
+        std::thread::spawn(move || {
+            let send = |event| Ok(sender.push_custom_event(event)?);
+            if let Err(e) = rocketbooth::connect_serial(&settings, &reader_port, send) {
+                eprintln!("Serial controller stopped: {e}");
+            }
+        });
+        port
+    });
     let display_mode = video_subsystem.display_mode(0, 0)?;
 
     let window = video_subsystem
@@ -57,10 +93,31 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut canvas = window.into_canvas().accelerated().present_vsync().build()?;
 
     let texture_creator = canvas.texture_creator();
-    let mut context = context_builder.build(&texture_creator)?;
+    let mut context = context_builder.build(&texture_creator, canvas.output_size()?)?;
+    #[cfg(feature = "gpio")]
+    if !context.config.gpio_outputs.is_empty() {
+        let backend: Box<dyn rocketbooth::GpioBackend> = Box::new(rocketbooth::ChipGpio::default());
+        match rocketbooth::GpioOutputs::open(backend, context.config.gpio_outputs.clone()) {
+            Ok(outputs) => context.gpio_outputs = Some(outputs),
+            Err(e) => eprintln!("GPIO outputs unavailable: {e}"),
+        }
+    }
+    context.serial = serial;
     let mut state = State::default();
+    // A display mounted on its side is drawn on a texture first, then turned into place.
+    let mut screen_texture = if context.screen.is_rotated() {
+        let (width, height) = context.screen.size();
+        Some(texture_creator.create_texture_target(None, width, height)?)
+    } else {
+        None
+    };
 
-    state.render(&mut canvas, &mut context)?;
+    draw(
+        &mut state,
+        &mut canvas,
+        &mut context,
+        screen_texture.as_mut(),
+    )?;
 
     let mut event_pump = sdl_context.event_pump()?;
 
@@ -68,7 +125,64 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     loop {
         state = state.handle_event(event_pump.poll_iter(), &mut context)?;
-        state.render(&mut canvas, &mut context)?;
+        draw(
+            &mut state,
+            &mut canvas,
+            &mut context,
+            screen_texture.as_mut(),
+        )?;
         // The rest of the game loop goes here...
     }
 }
+
+/// Sends a command to the running booth through its control socket, printing the reply.
+fn ctl(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = args.into_iter().peekable();
+    let config_path = match args.next_if(|arg| arg == "-c") {
+        Some(_) => args.next().ok_or(CTL_USAGE)?,
+        None => String::from("Rocketbooth.toml"),
+    };
+    let command = match (args.next().as_deref(), args.next(), args.next()) {
+        (Some("status"), None, None) => ControlCommand::Status,
+        (Some("trigger"), None, None) => ControlCommand::Trigger,
+        (Some("pause"), None, None) => ControlCommand::Pause,
+        (Some("resume"), None, None) => ControlCommand::Resume,
+        (Some("reprint"), copies, None) => ControlCommand::Reprint {
+            copies: copies.map_or(Ok(1), |copies| copies.parse())?,
+        },
+        (Some("reload"), None, None) => ControlCommand::Reload,
+        (Some("shutdown"), None, None) => ControlCommand::Shutdown,
+        _ => return Err(CTL_USAGE.into()),
+    };
+    let config: Config = toml::from_str(&std::fs::read_to_string(&config_path)?)?;
+    let control = config
+        .control
+        .ok_or_else(|| format!("{config_path} has no [control] socket set up"))?;
+    let reply = rocketbooth::send_command(&control.socket_paths(config_path.as_ref()), command)?;
+    println!("{}", serde_json::to_string(&reply)?);
+    match reply.error {
+        Some(error) if !reply.ok => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+fn draw<'t, T>(
+    state: &mut State<'t>,
+    canvas: &mut Canvas<Window>,
+    context: &mut Context<'t, T>,
+    screen_texture: Option<&mut Texture>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match screen_texture {
+        Some(screen_texture) => {
+            let mut result = Ok(());
+            canvas.with_texture_canvas(screen_texture, |canvas| {
+                result = state.render(canvas, context);
+            })?;
+            result?;
+            context.screen.draw_rotated(canvas, screen_texture)?;
+        }
+        None => state.render(canvas, context)?,
+    }
+    canvas.present();
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::{
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use image::RgbImage;
+use rocketbooth_libav::Frame;
+use sdl2::render::{Texture, TextureCreator};
+
+use crate::{
+    capture::{open_backend, CaptureBackend},
+    image_libav::frame_to_image,
+    libav_sdl2::FrameTextureManager,
+    video_input::ConnectionStatus,
+    CaptureCommand, CaptureSource, VideoSource,
+};
+
+/// Long-lived access to the video source, shared by every session.
+///
+/// States `acquire` the camera while they need a preview and `release` it when the booth goes
+/// idle. A released camera keeps streaming for `keep_warm` so the next guest doesn't have to
+/// wait for the device to open and its exposure to settle.
+pub struct Camera<'t, T> {
+    video_source: VideoSource,
+    capture_source: Option<CaptureSource>,
+    capture_command: Option<CaptureCommand>,
+    backend: Option<Box<dyn CaptureBackend>>,
+    textures: FrameTextureManager<'t, T>,
+    idle_since: Option<Instant>,
+    keep_warm: Duration,
+}
+
+impl<'t, T> Camera<'t, T> {
+    pub fn new(
+        texture_creator: &'t TextureCreator<T>,
+        video_source: VideoSource,
+        capture_source: Option<CaptureSource>,
+        capture_command: Option<CaptureCommand>,
+    ) -> Self {
+        let keep_warm = Duration::try_from_secs_f64(video_source.keep_warm).unwrap_or_default();
+        Self {
+            textures: FrameTextureManager::new(video_source.display_size, texture_creator),
+            video_source,
+            capture_source,
+            capture_command,
+            backend: None,
+            idle_since: None,
+            keep_warm,
+        }
+    }
+
+    /// Opens the video source unless it is already open, and marks the camera as in use.
+    pub fn acquire(&mut self) -> crate::Result<()> {
+        if self.backend.is_none() {
+            self.backend = Some(open_backend(
+                &self.video_source,
+                self.capture_source.as_ref(),
+                self.capture_command.as_ref(),
+            ));
+        }
+        self.idle_since = None;
+        Ok(())
+    }
+
+    /// Marks the camera as no longer in use. It is closed by `update` once it has been idle for
+    /// longer than `keep_warm`.
+    pub fn release(&mut self) {
+        if self.backend.is_some() && self.idle_since.is_none() {
+            self.idle_since = Some(Instant::now());
+        }
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        if self
+            .idle_since
+            .is_some_and(|idle_since| now >= idle_since + self.keep_warm)
+        {
+            self.backend = None;
+            self.textures.clear();
+            self.idle_since = None;
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.backend.is_some()
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.backend
+            .as_ref()
+            .map_or(ConnectionStatus::Connecting, |backend| backend.status())
+    }
+
+    /// Takes a photo in the background, using the current preview frame if the backend can't
+    /// do better.
+    pub fn capture_still(&mut self) -> crate::Result<JoinHandle<RgbImage>> {
+        let backend = self.backend.as_mut().ok_or("Camera is not open")?;
+        let preview = frame_to_image(
+            self.textures
+                .frame_ref()
+                .ok_or("Trying to capture before device is ready")?,
+        )?;
+        Ok(backend.capture_still(preview))
+    }
+
+    pub fn frame_ref(&self) -> Option<&Frame> {
+        self.textures.frame_ref()
+    }
+
+    pub fn texture_ref(&mut self) -> Option<&Texture<'t>> {
+        if let Some(frame) = self.backend.as_mut()?.take_preview_frame() {
+            self.textures.update(frame);
+        }
+        self.textures.texture_ref()
+    }
+
+    pub fn texture_mut(&mut self) -> Option<&mut Texture<'t>> {
+        self.backend.as_ref()?;
+        self.textures.texture_mut()
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    sync::atomic::{AtomicU64, Ordering},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use image::RgbImage;
+use rocketbooth_libav::Frame;
+
+use crate::{
+    image_libav::grab_image,
+    video_input::{ConnectionStatus, VideoReader},
+    CaptureCommand, CaptureSource, VideoSource,
+};
+
+/// A camera as seen by the booth: a stream of preview frames, plus a way to take the photos
+/// themselves.
+pub trait CaptureBackend {
+    /// Takes the most recent preview frame, if one has arrived since the last call.
+    fn take_preview_frame(&mut self) -> Option<Frame>;
+
+    fn status(&self) -> ConnectionStatus;
+
+    /// Takes a photo in the background. `preview` is the current preview frame; backends that
+    /// fail to take a better photo should fall back to it rather than lose the shot.
+    fn capture_still(&mut self, preview: RgbImage) -> JoinHandle<RgbImage>;
+}
+
+/// Opens the backend described by the configuration: the external command if there is one,
+/// otherwise libav.
+pub fn open_backend(
+    video_source: &VideoSource,
+    capture_source: Option<&CaptureSource>,
+    capture_command: Option<&CaptureCommand>,
+) -> Box<dyn CaptureBackend> {
+    match capture_command {
+        Some(capture_command) => Box::new(CommandCapture::new(video_source, capture_command)),
+        None => Box::new(LibavCapture::new(video_source, capture_source)),
+    }
+}
+
+/// Previews and takes photos through libav, either from the preview video itself or from a
+/// separate capture source.
+pub struct LibavCapture {
+    reader: VideoReader,
+    video_source: VideoSource,
+    capture_source: Option<CaptureSource>,
+}
+
+impl LibavCapture {
+    pub fn new(video_source: &VideoSource, capture_source: Option<&CaptureSource>) -> Self {
+        Self {
+            reader: VideoReader::spawn(video_source),
+            video_source: video_source.clone(),
+            capture_source: capture_source.cloned(),
+        }
+    }
+}
+
+impl CaptureBackend for LibavCapture {
+    fn take_preview_frame(&mut self) -> Option<Frame> {
+        self.reader.take_frame()
+    }
+
+    fn status(&self) -> ConnectionStatus {
+        self.reader.status()
+    }
+
+    fn capture_still(&mut self, preview: RgbImage) -> JoinHandle<RgbImage> {
+        let Some(capture_source) = self.capture_source.clone() else {
+            return std::thread::spawn(move || preview);
+        };
+        // Most devices can only be opened once, so the preview has to let go of it first.
+        let device_lock = (capture_source.source.path == self.video_source.path)
+            .then(|| self.reader.device_lock());
+        std::thread::spawn(move || {
+            let result = match device_lock {
+                Some(device_lock) => device_lock.with_device(|| grab_image(&capture_source)),
+                None => grab_image(&capture_source),
+            };
+            result.unwrap_or_else(|e| {
+                eprintln!(
+                    "Capturing from {} failed, using the preview frame instead: {e}",
+                    capture_source.source.path
+                );
+                preview
+            })
+        })
+    }
+}
+
+/// Previews through libav, eg from a DSLR's liveview, but takes photos by running an external
+/// command such as gphoto2 and reading the file it writes.
+pub struct CommandCapture {
+    reader: VideoReader,
+    capture_command: CaptureCommand,
+}
+
+impl CommandCapture {
+    pub fn new(video_source: &VideoSource, capture_command: &CaptureCommand) -> Self {
+        Self {
+            reader: VideoReader::spawn(video_source),
+            capture_command: capture_command.clone(),
+        }
+    }
+}
+
+impl CaptureBackend for CommandCapture {
+    fn take_preview_frame(&mut self) -> Option<Frame> {
+        self.reader.take_frame()
+    }
+
+    fn status(&self) -> ConnectionStatus {
+        self.reader.status()
+    }
+
+    fn capture_still(&mut self, preview: RgbImage) -> JoinHandle<RgbImage> {
+        let capture_command = self.capture_command.clone();
+        let device_lock = capture_command
+            .pause_preview
+            .then(|| self.reader.device_lock());
+        std::thread::spawn(move || {
+            let result = match device_lock {
+                Some(device_lock) => {
+                    device_lock.with_device(|| run_capture_command(&capture_command))
+                }
+                None => run_capture_command(&capture_command),
+            };
+            result.unwrap_or_else(|e| {
+                eprintln!("Capture command failed, using the preview frame instead: {e}");
+                preview
+            })
+        })
+    }
+}
+
+/// Runs the capture command with the path it should write the photo to appended, and reads the
+/// photo back.
+pub fn run_capture_command(capture_command: &CaptureCommand) -> crate::Result<RgbImage> {
+    let (program, args) = capture_command
+        .command
+        .split_first()
+        .ok_or("Capture command is empty")?;
+    let path = still_path();
+    let _ = std::fs::remove_file(&path);
+    let child = Command::new(program).args(args).arg(&path).spawn()?;
+    let timeout = Duration::try_from_secs_f64(capture_command.timeout).unwrap_or_default();
+    wait_with_timeout(child, timeout)?;
+    let image = read_still(&path);
+    let _ = std::fs::remove_file(&path);
+    image
+}
+
+/// A new path for each photo, since a slow capture can still be running when the next starts.
+/// It ends in `.jpg` for commands that go by the extension, but the photo can be in any format
+/// the booth reads, eg PNG.
+fn still_path() -> PathBuf {
+    static CAPTURES: AtomicU64 = AtomicU64::new(0);
+    let capture = CAPTURES.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "rocketbooth-still-{}-{capture}.jpg",
+        std::process::id()
+    ))
+}
+
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> crate::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(format!("Capture command exited with {status}").into())
+            };
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err("Capture command timed out".into());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn read_still(path: &Path) -> crate::Result<RgbImage> {
+    if !path.exists() {
+        return Err(format!("Capture command did not write {}", path.display()).into());
+    }
+    // The format comes from the file itself, whatever the path's extension.
+    let reader = image::ImageReader::open(path)?.with_guessed_format()?;
+    Ok(reader.decode()?.into_rgb8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A capture command that runs `script` with `sh`, which gets the path to write the photo
+    /// to as `$0`.
+    fn stand_in(script: &str, timeout: f64) -> CaptureCommand {
+        CaptureCommand {
+            command: vec!["sh".into(), "-c".into(), script.into()],
+            timeout,
+            pause_preview: false,
+        }
+    }
+
+    #[test]
+    fn the_photo_written_is_read_whatever_its_format() {
+        let photo =
+            std::env::temp_dir().join(format!("rocketbooth-test-{}-photo.png", std::process::id()));
+        RgbImage::from_pixel(4, 3, image::Rgb([200, 100, 50]))
+            .save(&photo)
+            .unwrap();
+        let command = stand_in(&format!("cp '{}' \"$0\"", photo.display()), 5.0);
+        let image = run_capture_command(&command).unwrap();
+        std::fs::remove_file(photo).unwrap();
+        assert_eq!(image.dimensions(), (4, 3));
+        assert_eq!(image.get_pixel(1, 1), &image::Rgb([200, 100, 50]));
+    }
+
+    #[test]
+    fn commands_that_fail_are_reported() {
+        let error = run_capture_command(&stand_in("exit 3", 5.0)).unwrap_err();
+        assert!(error.to_string().contains("exited with"), "{error}");
+    }
+
+    #[test]
+    fn commands_that_write_nothing_are_reported() {
+        let error = run_capture_command(&stand_in("true", 5.0)).unwrap_err();
+        assert!(error.to_string().contains("did not write"), "{error}");
+    }
+
+    #[test]
+    fn commands_that_take_too_long_are_killed() {
+        let child = Command::new("sh").args(["-c", "sleep 10"]).spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        let start = Instant::now();
+        let error = wait_with_timeout(child, Duration::from_millis(200)).unwrap_err();
+        assert_eq!(error.to_string(), "Capture command timed out");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // Safety: signal 0 only checks whether the process is still there.
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        let error = run_capture_command(&stand_in("sleep 10", 0.2)).unwrap_err();
+        assert_eq!(error.to_string(), "Capture command timed out");
+    }
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashMap, path::Path};
+
+use ab_glyph::FontArc;
+use image::{imageops, DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+use sdl2::rect::Rect;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ImageLayout,
+    text::{draw_text, fill_in, load_font, TextField},
+    theme::{place, Anchor, Scale},
+};
+
+/// The design of a saved image: a background, the photos placed in slots over it, an overlay on
+/// top, eg a frame with the event's logo, and lines of text such as the event's name and date.
+///
+/// Image paths are relative to the template file. Positions and sizes are in pixels of the
+/// saved image.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrintTemplate {
+    pub width: u32,
+    pub height: u32,
+    /// Stretched to cover the whole image, beneath the photos.
+    pub background: Option<String>,
+    /// Fills the image where there is no background image, as red, green and blue.
+    #[serde(default = "default_background_color")]
+    pub background_color: [u8; 3],
+    /// Stretched over the whole image, above the photos. Should be transparent where the photos
+    /// are meant to show through.
+    pub overlay: Option<String>,
+    #[serde(rename = "slot")]
+    pub slots: Vec<TemplateSlot>,
+    /// The font for text that doesn't name its own. Without one, the theme's font is used.
+    pub font: Option<String>,
+    /// Drawn over everything else, in order.
+    #[serde(default, rename = "text")]
+    pub texts: Vec<TextField>,
+}
+
+/// Where one of the photos goes in a template.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TemplateSlot {
+    /// Which photo of the session goes here, counting from 0. Photos may be used in more than
+    /// one slot.
+    pub shot: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Clockwise rotation of the photo in degrees; one of 0, 90, 180 or 270.
+    #[serde(default)]
+    pub rotation: u32,
+    #[serde(default = "default_slot_scale")]
+    pub scale: Scale,
+    #[serde(default)]
+    pub anchor: Anchor,
+}
+
+fn default_background_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+fn default_slot_scale() -> Scale {
+    Scale::Fill
+}
+
+/// Combines a session's photos into the image that is saved and printed.
+pub enum Compositor {
+    /// Arranges the photos according to the layout, at their own resolution.
+    Layout(ImageLayout),
+    Template {
+        template: Box<PrintTemplate>,
+        background: Option<RgbaImage>,
+        overlay: Option<RgbaImage>,
+        /// The font for each of the template's texts.
+        fonts: Vec<FontArc>,
+    },
+}
+
+impl Compositor {
+    /// Loads the template at `path` along with its images and fonts, checking that it only uses
+    /// the photos the layout takes and the text values in `names`. `theme_font` is used for
+    /// text when the template doesn't give a font.
+    pub fn from_template(
+        path: &Path,
+        layout: ImageLayout,
+        theme_font: Option<&Path>,
+        names: &[&str],
+    ) -> crate::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let template: PrintTemplate = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid print template {}: {e}", path.display()))?;
+        if template.width == 0 || template.height == 0 {
+            return Err(format!("{} must have a width and height", path.display()).into());
+        }
+        for slot in &template.slots {
+            if slot.shot >= layout.capture_count() {
+                return Err(format!(
+                    "{} uses photo {}, but the layout only takes {} photos (counting from 0)",
+                    path.display(),
+                    slot.shot,
+                    layout.capture_count()
+                )
+                .into());
+            }
+            if slot.rotation % 90 != 0 || slot.rotation >= 360 {
+                return Err(format!(
+                    "{}: slot rotation must be 0, 90, 180 or 270, not {}",
+                    path.display(),
+                    slot.rotation
+                )
+                .into());
+            }
+        }
+        for text in &template.texts {
+            text.validate(names)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        let template_dir = path.parent().unwrap_or(Path::new(""));
+        // Both images cover the whole template, so they're scaled to it once up front.
+        let load = |name: &Option<String>| -> crate::Result<Option<RgbaImage>> {
+            name.as_ref()
+                .map(|name| {
+                    let path = template_dir.join(name);
+                    let image = image::open(&path)
+                        .map_err(|e| format!("Failed to load {}: {e}", path.display()))?;
+                    Ok(imageops::resize(
+                        &image.into_rgba8(),
+                        template.width,
+                        template.height,
+                        imageops::FilterType::Triangle,
+                    ))
+                })
+                .transpose()
+        };
+        // Fonts are loaded once each, however many texts use them.
+        let mut loaded: HashMap<_, FontArc> = HashMap::new();
+        let mut fonts = Vec::new();
+        for text in &template.texts {
+            let font_path = match text.font.as_ref().or(template.font.as_ref()) {
+                Some(font) => template_dir.join(font),
+                None => theme_font
+                    .ok_or_else(|| {
+                        format!(
+                            "{} has text but no font; set one in the template or the theme",
+                            path.display()
+                        )
+                    })?
+                    .to_path_buf(),
+            };
+            let font = match loaded.get(&font_path) {
+                Some(font) => font.clone(),
+                None => {
+                    let font = load_font(&font_path)?;
+                    loaded.insert(font_path, font.clone());
+                    font
+                }
+            };
+            fonts.push(font);
+        }
+        Ok(Self::Template {
+            background: load(&template.background)?,
+            overlay: load(&template.overlay)?,
+            fonts,
+            template: Box::new(template),
+        })
+    }
+
+    /// Combines the photos, filling in the template's text from `values`.
+    pub fn compose(&self, photos: &[RgbImage], values: &HashMap<String, String>) -> RgbImage {
+        match self {
+            Self::Layout(layout) => compose_layout(*layout, photos),
+            Self::Template {
+                template,
+                background,
+                overlay,
+                fonts,
+            } => {
+                let mut canvas =
+                    compose_template(template, background.as_ref(), overlay.as_ref(), photos);
+                for (text, font) in template.texts.iter().zip(fonts) {
+                    draw_text(&mut canvas, text, font, &fill_in(&text.text, values));
+                }
+                DynamicImage::ImageRgba8(canvas).into_rgb8()
+            }
+        }
+    }
+}
+
+fn compose_layout(layout: ImageLayout, photos: &[RgbImage]) -> RgbImage {
+    let Some(first) = photos.first() else {
+        return RgbImage::new(1, 1);
+    };
+    let (width, height) = layout.dest_size(first.width(), first.height());
+    let mut final_image = RgbImage::from_pixel(width, height, Rgb(layout.background()));
+    for slot in layout.slots(width, height) {
+        let Some(photo) = photos.get(slot.shot) else {
+            continue;
+        };
+        // Rounding can leave a slot a pixel off the photo's own size.
+        let resized;
+        let photo = if photo.dimensions() == (slot.width, slot.height) {
+            photo
+        } else {
+            resized = imageops::resize(
+                photo,
+                slot.width,
+                slot.height,
+                imageops::FilterType::Triangle,
+            );
+            &resized
+        };
+        imageops::overlay(&mut final_image, photo, slot.x as i64, slot.y as i64);
+    }
+    final_image
+}
+
+fn compose_template(
+    template: &PrintTemplate,
+    background: Option<&RgbaImage>,
+    overlay: Option<&RgbaImage>,
+    photos: &[RgbImage],
+) -> RgbaImage {
+    let [r, g, b] = template.background_color;
+    let mut canvas = RgbaImage::from_pixel(template.width, template.height, Rgba([r, g, b, 255]));
+    if let Some(background) = background {
+        imageops::overlay(&mut canvas, background, 0, 0);
+    }
+    for slot in &template.slots {
+        if let Some(photo) = photos.get(slot.shot) {
+            draw_slot(&mut canvas, slot, photo);
+        }
+    }
+    if let Some(overlay) = overlay {
+        imageops::overlay(&mut canvas, overlay, 0, 0);
+    }
+    canvas
+}
+
+fn draw_slot(canvas: &mut RgbaImage, slot: &TemplateSlot, photo: &RgbImage) {
+    let photo = DynamicImage::ImageRgb8(photo.clone());
+    let photo = match slot.rotation {
+        90 => photo.rotate90(),
+        180 => photo.rotate180(),
+        270 => photo.rotate270(),
+        _ => photo,
+    };
+    let area = Rect::new(slot.x, slot.y, slot.width.max(1), slot.height.max(1));
+    let dest = place(photo.width(), photo.height(), slot.scale, slot.anchor, area);
+    let photo = imageops::resize(
+        &photo.into_rgba8(),
+        dest.width(),
+        dest.height(),
+        imageops::FilterType::Triangle,
+    );
+    // Filled photos spill out of their slot; only the part inside it is drawn.
+    let Some(visible) = dest.intersection(area) else {
+        return;
+    };
+    let photo = imageops::crop_imm(
+        &photo,
+        (visible.x() - dest.x()) as u32,
+        (visible.y() - dest.y()) as u32,
+        visible.width(),
+        visible.height(),
+    );
+    imageops::overlay(
+        canvas,
+        &photo.to_image(),
+        visible.x() as i64,
+        visible.y() as i64,
+    );
+}
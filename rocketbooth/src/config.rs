@@ -1,24 +1,95 @@
 use std::{
     collections::HashMap,
-    ops::{Div, Mul, Sub},
+    path::{Path, PathBuf},
 };
 
+use sdl2::{keyboard::Keycode, rect::Point};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::{framing::Framing, widget::Area};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct VideoSource {
     pub path: String,
     pub format: Option<String>,
     pub video_codec: Option<String>,
     pub display_size: Option<(usize, usize)>,
+    /// Maximum number of bytes read while detecting the input format.
+    pub probesize: Option<i64>,
+    /// Maximum duration, in microseconds, of input analyzed to detect stream parameters.
+    pub analyzeduration: Option<u64>,
+    /// Seconds allowed for opening the source before it's considered unavailable.
+    #[serde(default = "default_open_timeout")]
+    pub open_timeout: f64,
+    /// Seconds without new data after which the source is considered lost and reopened.
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: f64,
+    /// Seconds to keep the source open after the booth goes idle, so the next session starts
+    /// with a ready, settled camera.
+    #[serde(default = "default_keep_warm")]
+    pub keep_warm: f64,
     #[serde(default)]
     pub options: HashMap<String, String>,
 }
 
+impl VideoSource {
+    /// Checks the source configured as `section`, eg "video_source".
+    pub fn validate(&self, section: &str) -> Result<(), String> {
+        let timeouts = [
+            ("open_timeout", self.open_timeout),
+            ("read_timeout", self.read_timeout),
+        ];
+        for (name, value) in timeouts {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!(
+                    "{section}.{name} must be a number of seconds above 0, not {value}"
+                ));
+            }
+        }
+        if !(self.keep_warm.is_finite() && self.keep_warm >= 0.0) {
+            return Err(format!(
+                "{section}.keep_warm must be a number of seconds, not {}",
+                self.keep_warm
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A separate video source used only for taking photos, eg the same webcam in a higher
+/// resolution mode than the preview.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CaptureSource {
+    #[serde(flatten)]
+    pub source: VideoSource,
+    /// Frames to discard after opening the source, giving the camera time to adjust exposure.
+    #[serde(default)]
+    pub warmup_frames: usize,
+}
+
+/// An external program that takes each photo, eg `gphoto2` driving a DSLR. The path the photo
+/// should be written to is appended to the command.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CaptureCommand {
+    pub command: Vec<String>,
+    /// Seconds to wait for the command before giving up and using the preview frame.
+    #[serde(default = "default_capture_timeout")]
+    pub timeout: f64,
+    /// Close the preview video while the command runs, for cameras that can't do both at once.
+    #[serde(default)]
+    pub pause_preview: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ImageSettings {
     #[serde(default)]
     pub layout: ImageLayout,
+    /// The part of each camera frame that makes up the photo, on screen and when saved.
+    #[serde(default)]
+    pub framing: Framing,
+    /// Print template describing the saved image, relative to the config file. Without one, the
+    /// photos are saved side by side according to the layout.
+    pub template: Option<String>,
     pub prefix: Option<String>,
     pub format: Option<String>,
     pub post_command: Option<Vec<String>>,
@@ -26,6 +97,136 @@ pub struct ImageSettings {
     pub enable_post_command: bool,
 }
 
+/// Printing each saved image through CUPS, following the job so that problems at the printer
+/// can be shown on screen.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrinterSettings {
+    /// The CUPS queue to print to. Without one, the default printer is used.
+    pub name: Option<String>,
+    /// The paper size, as a CUPS media name, eg "4x6" or "na_index-4x6_4x6in".
+    pub media: Option<String>,
+    /// Print to the edges of the paper, on printers that support it.
+    #[serde(default)]
+    pub borderless: bool,
+    /// Further CUPS job options, eg `print-quality = "5"`.
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+    /// Seconds between checks on the job and the printer.
+    #[serde(default = "default_print_poll_interval")]
+    pub poll_interval: f64,
+    /// Seconds after which an unfinished job is cancelled, or 0 to wait as long as it takes,
+    /// eg for someone to refill the paper.
+    #[serde(default)]
+    pub timeout: f64,
+}
+
+impl PrinterSettings {
+    /// The CUPS options for each job, with `options` taking precedence.
+    pub fn job_options(&self) -> Vec<(String, String)> {
+        let mut options = Vec::new();
+        if let Some(media) = &self.media {
+            options.push(("media".into(), media.clone()));
+        }
+        if self.borderless {
+            // Driverless printers take the scaling; Gutenprint drivers, as used for Selphy
+            // printers, take the Borderless option. Each ignores the other's.
+            options.push(("print-scaling".into(), "fill".into()));
+            options.push(("Borderless".into(), "True".into()));
+        }
+        options.retain(|(name, _)| !self.options.contains_key(name));
+        options.extend(self.options.clone());
+        options
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !cfg!(feature = "cups") {
+            return Err("printer needs rocketbooth to be built with the cups feature".into());
+        }
+        if !(self.poll_interval.is_finite() && self.poll_interval > 0.0) {
+            return Err(format!(
+                "printer.poll_interval must be a number of seconds, not {}",
+                self.poll_interval
+            ));
+        }
+        if !(self.timeout.is_finite() && self.timeout >= 0.0) {
+            return Err(format!(
+                "printer.timeout must be a number of seconds, not {}",
+                self.timeout
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Letting guests choose how many prints to make of each session, within limits that keep the
+/// paper and ink lasting the event.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Copies {
+    /// The most prints a guest can choose for one session. With 1, no choice is offered.
+    #[serde(default = "default_max_copies")]
+    pub max_per_session: u32,
+    /// The number chosen when the guest doesn't pick one in time.
+    #[serde(default = "default_copies")]
+    pub default: u32,
+    /// The most prints for the whole event. Once they've been made, sessions are still saved
+    /// but no longer printed. Without one, there's no limit.
+    pub event_quota: Option<u32>,
+    /// File counting the prints made towards `event_quota`, relative to the config file, so the
+    /// count survives restarts. Delete it to start a new event.
+    #[serde(default = "default_quota_file")]
+    pub quota_file: String,
+}
+
+impl Copies {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_per_session == 0 {
+            return Err("copies.max_per_session must be at least 1".into());
+        }
+        if !(1..=self.max_per_session).contains(&self.default) {
+            return Err(format!(
+                "copies.default must be from 1 to max_per_session ({}), not {}",
+                self.max_per_session, self.default
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Showing the photos after the last shot, so guests can retake them before anything is saved
+/// or printed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Review {
+    /// What happens when the guest doesn't choose in time.
+    #[serde(default)]
+    pub timeout_action: ReviewAction,
+}
+
+/// A choice on the review screen.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewAction {
+    /// Save the photos, and print them if printing is set up.
+    #[default]
+    Keep,
+    /// Take all the photos again.
+    RetakeAll,
+    /// Take the photo the guest picked again.
+    RetakeShot,
+    /// Throw the photos away without saving them.
+    Discard,
+}
+
+impl Review {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timeout_action == ReviewAction::RetakeShot {
+            return Err(
+                "review.timeout_action can't be retake_shot, since no photo has been picked".into(),
+            );
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize,Deserialize, Debug)]
 pub struct Controls {
     #[serde(default="default_post_command")]
@@ -34,6 +235,10 @@ pub struct Controls {
     pub enable_backspace_command: bool,
     #[serde(default="default_backspace_command")]
     pub backspace_command: Vec<String>,
+    #[serde(default)]
+    pub touch: TouchSettings,
+    #[serde(default)]
+    pub bindings: Bindings,
 }
 
 impl Default for Controls {
@@ -42,16 +247,681 @@ impl Default for Controls {
             enable_exit_inputs: true,
             enable_backspace_command: false,
             backspace_command: vec![],
+            touch: TouchSettings::default(),
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+/// Something an input can make the booth do. What it does depends on the screen.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Move on to the next screen, as tapping the screen does.
+    Advance,
+    /// Give up on the session: the photos are discarded on the review screen, and none are
+    /// printed on the copies screen. Elsewhere, the booth goes back to idle.
+    Cancel,
+    /// Take all the photos again, on the review screen.
+    Retake,
+    /// Keep the photos on the review screen, or print the default number on the copies screen.
+    Print,
+    /// Open the operator menu, while the booth is idle.
+    OperatorMenu,
+    /// Run `controls.backspace_command`, if it's enabled.
+    RunCommand,
+    /// Stop the booth, if `controls.enable_exit_inputs` is set.
+    Shutdown,
+}
+
+/// Which inputs make the booth do what.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bindings {
+    /// Keys by their SDL names, eg "Return", "Space" or "PageDown". These replace the default
+    /// keys rather than adding to them.
+    #[serde(default = "default_key_bindings")]
+    pub keys: HashMap<String, Action>,
+    /// What a click or tap does on the screens without buttons, outside any of the `regions`.
+    #[serde(default = "default_tap")]
+    pub tap: Action,
+    /// Parts of the screen that do something else when clicked or tapped, on every screen.
+    /// Where they overlap, the first listed wins.
+    #[serde(default)]
+    pub regions: Vec<RegionBinding>,
+    /// Events by name, sent by other inputs such as the `[serial]` controller.
+    #[serde(default = "default_event_bindings")]
+    pub events: HashMap<String, Action>,
+    /// The GPIO lines bound here before the `[[gpio]]` buttons replaced them, kept only to turn
+    /// away configs that still bind them.
+    #[serde(default, skip_serializing)]
+    gpio: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct RegionBinding {
+    pub area: Area,
+    pub action: Action,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: default_key_bindings(),
+            tap: default_tap(),
+            regions: vec![],
+            events: default_event_bindings(),
+            gpio: None,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gpio.is_some() {
+            return Err(
+                "controls.bindings.gpio has been replaced by [[gpio]] sections, each with \
+                a line and what a press of it does, eg line = 2 and press = \"advance\""
+                    .into(),
+            );
+        }
+        if let Some(name) = (self.keys.keys()).find(|name| Keycode::from_name(name).is_none()) {
+            return Err(format!("controls.bindings.keys has no key called {name:?}"));
+        }
+        for region in &self.regions {
+            region.area.validate()?;
+        }
+        Ok(())
+    }
+
+    /// The action bound to a key, if any.
+    pub fn key(&self, keycode: Keycode) -> Option<Action> {
+        (self.keys.iter())
+            .find(|(name, _)| Keycode::from_name(name) == Some(keycode))
+            .map(|(_, action)| *action)
+    }
+
+    /// The action of the region at `point` on a screen of the given size, if any.
+    pub fn region(&self, point: Point, screen_size: (u32, u32)) -> Option<Action> {
+        (self.regions.iter())
+            .find(|region| region.area.rect(screen_size).contains_point(point))
+            .map(|region| region.action)
+    }
+}
+
+/// A button wired to a GPIO line, read with the `gpio` feature.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GpioInput {
+    /// The GPIO chip the line is on.
+    #[serde(default = "default_gpio_chip")]
+    pub chip: String,
+    /// The line's offset on the chip, which on a Raspberry Pi is its BCM GPIO number.
+    pub line: u32,
+    /// The change on the line when the button is pressed: "rising", or "falling" for a button
+    /// that pulls the line low.
+    #[serde(default)]
+    pub edge: Edge,
+    /// A pull up or down resistor to hold the line steady while the button isn't pressed, on
+    /// kernels from 5.5. Left as it is by default.
+    #[serde(default)]
+    pub bias: Bias,
+    /// Changes on the line within this many seconds of the last are ignored, as the button's
+    /// contacts bounce.
+    #[serde(default = "default_gpio_debounce")]
+    pub debounce: f64,
+    /// What a press of the button does.
+    #[serde(default = "default_tap")]
+    pub press: Action,
+    /// What holding the button down for `long_press_time` does. Buttons with one act when
+    /// they're let go, rather than as soon as they're pressed, to tell the two apart.
+    pub long_press: Option<Action>,
+    #[serde(default = "default_long_press")]
+    pub long_press_time: f64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    #[default]
+    Rising,
+    Falling,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Bias {
+    #[default]
+    AsIs,
+    PullUp,
+    PullDown,
+    Disabled,
+}
+
+impl GpioInput {
+    pub fn validate(&self) -> Result<(), String> {
+        let durations = [
+            ("debounce", self.debounce),
+            ("long_press_time", self.long_press_time),
+        ];
+        for (name, value) in durations {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!(
+                    "gpio.{name} must be a number of seconds, not {value}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A light, flash or relay wired to a GPIO line, driven with the `gpio` feature.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GpioOutput {
+    /// The GPIO chip the line is on.
+    #[serde(default = "default_gpio_chip")]
+    pub chip: String,
+    /// The line's offset on the chip, which on a Raspberry Pi is its BCM GPIO number.
+    pub line: u32,
+    /// Turn the line low for on, eg for a relay board that switches on a low input.
+    #[serde(default)]
+    pub active_low: bool,
+    /// The screens the line is on for, eg `["idle", "welcome"]` for the light in the booth's
+    /// button.
+    #[serde(default)]
+    pub on: Vec<Phase>,
+    /// The screens the line blinks on and off during, eg `["countdown"]`.
+    #[serde(default)]
+    pub blink: Vec<Phase>,
+    /// Seconds the line is on, and then off, while it blinks.
+    #[serde(default = "default_blink_interval")]
+    pub blink_interval: f64,
+    /// Seconds the line is turned on for as each photo is taken, eg to fire a flash. 0 for
+    /// never.
+    #[serde(default)]
+    pub flash: f64,
+}
+
+/// The stages of a session, as the booth's screens show them.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Idle,
+    Welcome,
+    Explainer,
+    /// Taking the photos, including the gaps between them.
+    Countdown,
+    Review,
+    Copies,
+    /// Showing the photos while they're saved, and after.
+    Debrief,
+    PrinterError,
+    Operator,
+}
+
+impl Phase {
+    /// The phase's name as it's reported to the serial controller and the control socket.
+    pub fn state_name(&self) -> &'static str {
+        match self {
+            Phase::Idle => "idle",
+            Phase::Welcome => "welcome",
+            Phase::Explainer => "explainer",
+            Phase::Countdown => "capture",
+            Phase::Review => "review",
+            Phase::Copies => "copies",
+            Phase::Debrief => "debrief",
+            Phase::PrinterError => "printer_error",
+            Phase::Operator => "operator",
+        }
+    }
+}
+
+/// An external controller on a serial port, eg an Arduino with the booth's buttons, a coin
+/// acceptor and lights. It sends commands a line at a time, eg `PRESS` or `COIN 1`, which are
+/// looked up by their first word in `controls.bindings.events`, and it's sent a line such as
+/// `STATE capture 3` each time the booth moves on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SerialSettings {
+    /// The serial device, eg "/dev/ttyACM0" or "/dev/ttyUSB0".
+    pub path: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+}
+
+/// The baud rates a serial port can be set to.
+pub const BAUD_RATES: [u32; 8] = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+impl SerialSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !BAUD_RATES.contains(&self.baud_rate) {
+            return Err(format!(
+                "serial.baud_rate must be one of {BAUD_RATES:?}, not {}",
+                self.baud_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The control socket, which takes commands such as `status`, `trigger` or `reprint` as lines of
+/// JSON. Anyone who can write to it can run the booth, so it's best kept in a directory only the
+/// operator can get into.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ControlSettings {
+    /// The socket's path, relative to the config file.
+    #[serde(default = "default_control_socket")]
+    pub socket: String,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            socket: default_control_socket(),
+        }
+    }
+}
+
+impl ControlSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.socket.is_empty() {
+            return Err("control.socket can't be empty".into());
         }
+        Ok(())
+    }
+
+    /// Where the socket can be, for the booth configured by the file at `config_path`, in the
+    /// order they're tried: the configured path, then the same name in `$XDG_RUNTIME_DIR` and
+    /// the temporary directory, for when the config file's somewhere a socket can't be made, eg
+    /// a FAT formatted USB stick.
+    pub fn socket_paths(&self, config_path: &Path) -> Vec<PathBuf> {
+        let mut path = PathBuf::from(config_path);
+        path.pop();
+        path.push(&self.socket);
+        let mut paths = vec![path.clone()];
+        let Some(name) = path.file_name() else {
+            return paths;
+        };
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+        for dir in runtime_dir.into_iter().chain([std::env::temp_dir()]) {
+            let fallback = dir.join(name);
+            if !paths.contains(&fallback) {
+                paths.push(fallback);
+            }
+        }
+        paths
+    }
+}
+
+impl GpioOutput {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.blink_interval.is_finite() && self.blink_interval > 0.0) {
+            return Err(format!(
+                "gpio_outputs.blink_interval must be a number of seconds, not {}",
+                self.blink_interval
+            ));
+        }
+        if !(self.flash.is_finite() && self.flash >= 0.0) {
+            return Err(format!(
+                "gpio_outputs.flash must be a number of seconds, not {}",
+                self.flash
+            ));
+        }
+        if let Some(phase) = self.on.iter().find(|phase| self.blink.contains(phase)) {
+            return Err(format!(
+                "gpio_outputs can't both turn line {} on and blink it for {phase:?}",
+                self.line
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How touches on a touch screen are read.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TouchSettings {
+    /// Touches starting within this many seconds of the last one are ignored, for screens that
+    /// report a single tap more than once.
+    #[serde(default = "default_debounce")]
+    pub debounce: f64,
+    /// Recognise swipes and long presses, rather than treating every touch as a tap.
+    #[serde(default)]
+    pub gestures: bool,
+    /// How far a finger has to move sideways to swipe, as a fraction of the screen's width.
+    #[serde(default = "default_swipe_distance")]
+    pub swipe_distance: f64,
+    /// Seconds a finger has to be held still to open the operator menu.
+    #[serde(default = "default_long_press")]
+    pub long_press: f64,
+}
+
+impl Default for TouchSettings {
+    fn default() -> Self {
+        Self {
+            debounce: default_debounce(),
+            gestures: false,
+            swipe_distance: default_swipe_distance(),
+            long_press: default_long_press(),
+        }
+    }
+}
+
+impl TouchSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("debounce", self.debounce), ("long_press", self.long_press)] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!(
+                    "controls.touch.{name} must be a number of seconds, not {value}"
+                ));
+            }
+        }
+        if !(self.swipe_distance > 0.0 && self.swipe_distance <= 1.0) {
+            return Err(format!(
+                "controls.touch.swipe_distance is a fraction of the screen's width, eg 0.2, not {}",
+                self.swipe_distance
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Images shown by the booth when no theme is configured, in addition to the fixed files in the
+/// prompts directory.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Prompts {
+    /// Images for the countdown before each photo, relative to the config file. Without this,
+    /// the numbered images in `prompts/countdown` are used if that directory exists.
+    pub countdown: Option<Vec<String>>,
+}
+
+/// How long each screen stays up, in seconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Timing {
+    /// Time on the welcome screen before the booth goes back to idle.
+    #[serde(default = "default_screen_timeout")]
+    pub welcome_timeout: f64,
+    /// Time on the explainer screen before the booth goes back to idle.
+    #[serde(default = "default_screen_timeout")]
+    pub explainer_timeout: f64,
+    /// Length of the countdown before each photo.
+    #[serde(default = "default_countdown")]
+    pub countdown: f64,
+    /// Pause after each photo before the next countdown starts.
+    #[serde(default)]
+    pub shot_gap: f64,
+    /// Time on the review screen before its timeout action is taken.
+    #[serde(default = "default_review_timeout")]
+    pub review_timeout: f64,
+    /// Time on the screen choosing how many prints to make before the default is used.
+    #[serde(default = "default_copies_timeout")]
+    pub copies_timeout: f64,
+    /// Time on the operator menu before the booth goes back to idle.
+    #[serde(default = "default_screen_timeout")]
+    pub operator_timeout: f64,
+    /// Time the finished photos are shown before moving on.
+    #[serde(default = "default_debrief")]
+    pub debrief: f64,
+    /// Time on the welcome screen after a session ends, before the booth goes back to idle.
+    #[serde(default = "default_screen_timeout")]
+    pub welcome_after_session: f64,
+    /// Time on the welcome screen when the guest skips the end of the debrief.
+    #[serde(default = "default_debrief")]
+    pub welcome_after_skip: f64,
+}
+
+/// How the camera is mounted, and which views of the photos are mirrored.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Orientation {
+    /// Clockwise turn applied to the camera's frames, in degrees; one of 0, 90, 180 or 270. Use
+    /// 90 or 270 for a camera mounted sideways in a portrait booth.
+    #[serde(default)]
+    pub rotation: u32,
+    /// Mirror the live preview, so guests see themselves as in a mirror.
+    #[serde(default = "default_mirror")]
+    pub mirror_preview: bool,
+    /// Mirror the photos shown on screen after they're taken.
+    #[serde(default = "default_mirror")]
+    pub mirror_review: bool,
+    /// Mirror the saved image.
+    #[serde(default)]
+    pub mirror_saved: bool,
+    /// Clockwise turn applied to everything the booth draws, in degrees; one of 0, 90, 180 or
+    /// 270. Use 90 or 270 for a display mounted in portrait.
+    #[serde(default)]
+    pub display_rotation: u32,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self {
+            rotation: 0,
+            mirror_preview: default_mirror(),
+            mirror_review: default_mirror(),
+            mirror_saved: false,
+            display_rotation: 0,
+        }
+    }
+}
+
+impl Orientation {
+    pub fn validate(&self) -> Result<(), String> {
+        let rotations = [
+            ("rotation", self.rotation),
+            ("display_rotation", self.display_rotation),
+        ];
+        for (name, value) in rotations {
+            if ![0, 90, 180, 270].contains(&value) {
+                return Err(format!(
+                    "orientation.{name} must be 0, 90, 180 or 270, not {value}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The values text on the saved images can always use.
+const BUILT_IN_NAMES: [&str; 3] = ["event_name", "date", "session_number"];
+
+/// Details of the event the booth is at, for text on the saved images.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EventInfo {
+    /// Fills in `{event_name}`.
+    #[serde(default)]
+    pub name: String,
+    /// How `{date}` is written, in the `time` crate's format description syntax.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    /// Further values for text, eg `hashtag = "#SamAndAlex"` fills in `{hashtag}`.
+    #[serde(default)]
+    pub captions: HashMap<String, String>,
+}
+
+impl Default for EventInfo {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            date_format: default_date_format(),
+            captions: HashMap::new(),
+        }
+    }
+}
+
+impl EventInfo {
+    /// The names text can use: the built in values followed by the captions.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = BUILT_IN_NAMES.to_vec();
+        names.extend(self.captions.keys().map(|name| name.as_str()));
+        names
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        time::format_description::parse_borrowed::<1>(&self.date_format)
+            .map_err(|e| format!("Invalid event.date_format \"{}\": {e}", self.date_format))?;
+        for name in BUILT_IN_NAMES {
+            if self.captions.contains_key(name) {
+                return Err(format!(
+                    "event.captions can't replace the built in {{{name}}}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            welcome_timeout: default_screen_timeout(),
+            explainer_timeout: default_screen_timeout(),
+            countdown: default_countdown(),
+            shot_gap: 0.0,
+            review_timeout: default_review_timeout(),
+            copies_timeout: default_copies_timeout(),
+            operator_timeout: default_screen_timeout(),
+            debrief: default_debrief(),
+            welcome_after_session: default_screen_timeout(),
+            welcome_after_skip: default_debrief(),
+        }
+    }
+}
+
+impl Timing {
+    pub fn validate(&self) -> Result<(), String> {
+        let durations = [
+            ("welcome_timeout", self.welcome_timeout),
+            ("explainer_timeout", self.explainer_timeout),
+            ("countdown", self.countdown),
+            ("shot_gap", self.shot_gap),
+            ("review_timeout", self.review_timeout),
+            ("copies_timeout", self.copies_timeout),
+            ("operator_timeout", self.operator_timeout),
+            ("debrief", self.debrief),
+            ("welcome_after_session", self.welcome_after_session),
+            ("welcome_after_skip", self.welcome_after_skip),
+        ];
+        for (name, value) in durations {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("timing.{name} must be a number of seconds, not {value}"));
+            }
+        }
+        if self.countdown == 0.0 {
+            return Err("timing.countdown must be longer than 0 seconds".into());
+        }
+        Ok(())
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub video_source: VideoSource,
+    pub capture_source: Option<CaptureSource>,
+    pub capture_command: Option<CaptureCommand>,
     pub image: Option<ImageSettings>,
+    pub printer: Option<PrinterSettings>,
+    pub review: Option<Review>,
+    pub copies: Option<Copies>,
     #[serde(default)]
     pub controls: Controls,
+    /// Buttons on the GPIO pins. Without any listed, line 2 advances the booth, unless it's
+    /// one of the `gpio_outputs`; see `gpio_inputs`.
+    #[serde(default)]
+    pub gpio: Vec<GpioInput>,
+    /// Lights, flashes and relays on the GPIO pins.
+    #[serde(default)]
+    pub gpio_outputs: Vec<GpioOutput>,
+    /// A controller for buttons and lights on a serial port, instead of or as well as GPIO.
+    pub serial: Option<SerialSettings>,
+    /// A socket for scripting the running booth with `rocketbooth ctl`.
+    pub control: Option<ControlSettings>,
+    #[serde(default)]
+    pub timing: Timing,
+    /// Directory holding the `theme.toml` for the booth's screens, relative to the config file.
+    /// Without one, the fixed prompt files in the `prompts` directory are used.
+    pub theme: Option<String>,
+    #[serde(default)]
+    pub prompts: Prompts,
+    #[serde(default)]
+    pub event: EventInfo,
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+impl Config {
+    /// The GPIO buttons to read: those listed, or else the default button on line 2 as long as
+    /// it isn't driven as an output.
+    pub fn gpio_inputs(&self) -> Vec<GpioInput> {
+        if !self.gpio.is_empty() {
+            return self.gpio.clone();
+        }
+        let mut inputs = default_gpio();
+        inputs.retain(|input| {
+            !(self.gpio_outputs.iter())
+                .any(|output| output.chip == input.chip && output.line == input.line)
+        });
+        inputs
+    }
+
+    /// Checks for settings that parse but can't work together.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capture_source.is_some() && self.capture_command.is_some() {
+            return Err("capture_source and capture_command can't both be configured".into());
+        }
+        self.video_source.validate("video_source")?;
+        if let Some(capture_source) = &self.capture_source {
+            capture_source.source.validate("capture_source")?;
+        }
+        if let Some(image) = &self.image {
+            image.layout.validate()?;
+            image.framing.validate()?;
+        }
+        let has_post_command = self.image.as_ref().is_some_and(|image| {
+            image.enable_post_command && image.post_command.as_ref().is_some_and(|c| !c.is_empty())
+        });
+        if let Some(printer) = &self.printer {
+            printer.validate()?;
+            // The post command would print each session a second time, eg with lpr.
+            if has_post_command {
+                return Err(
+                    "printer replaces image.post_command; set enable_post_command = false".into(),
+                );
+            }
+        }
+        if let Some(review) = &self.review {
+            review.validate()?;
+        }
+        if let Some(copies) = &self.copies {
+            if self.printer.is_none() && !has_post_command {
+                return Err("copies needs a printer or an image.post_command to print with".into());
+            }
+            copies.validate()?;
+        }
+        self.controls.touch.validate()?;
+        self.controls.bindings.validate()?;
+        for input in &self.gpio {
+            input.validate()?;
+        }
+        for output in &self.gpio_outputs {
+            output.validate()?;
+        }
+        if let Some(serial) = &self.serial {
+            serial.validate()?;
+        }
+        if let Some(control) = &self.control {
+            control.validate()?;
+        }
+        let inputs = self.gpio_inputs();
+        let lines: Vec<_> = (inputs.iter().map(|input| (&input.chip, input.line)))
+            .chain((self.gpio_outputs.iter()).map(|output| (&output.chip, output.line)))
+            .collect();
+        for (i, (chip, line)) in lines.iter().enumerate() {
+            if lines[..i].contains(&(chip, *line)) {
+                return Err(format!(
+                    "gpio and gpio_outputs use line {line} on {chip} more than once"
+                ));
+            }
+        }
+        self.event.validate()?;
+        self.orientation.validate()?;
+        self.timing.validate()
+    }
 }
 
 fn default_post_command() -> bool {
@@ -62,51 +932,397 @@ fn default_backspace_command() -> Vec<String> {
     vec![]
 }
 
+fn default_key_bindings() -> HashMap<String, Action> {
+    [
+        ("Return", Action::Advance),
+        ("Space", Action::Advance),
+        ("R", Action::Retake),
+        ("D", Action::Cancel),
+        ("Delete", Action::Cancel),
+        ("Backspace", Action::RunCommand),
+        ("Escape", Action::Shutdown),
+        ("Q", Action::Shutdown),
+    ]
+    .into_iter()
+    .map(|(name, action)| (name.to_string(), action))
+    .collect()
+}
+
+fn default_event_bindings() -> HashMap<String, Action> {
+    HashMap::from([("PRESS".to_string(), Action::Advance)])
+}
+
+fn default_control_socket() -> String {
+    "rocketbooth.sock".into()
+}
+
+fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_tap() -> Action {
+    Action::Advance
+}
+
+fn default_gpio() -> Vec<GpioInput> {
+    vec![GpioInput {
+        chip: default_gpio_chip(),
+        line: 2,
+        edge: Edge::Rising,
+        bias: Bias::AsIs,
+        debounce: default_gpio_debounce(),
+        press: Action::Advance,
+        long_press: None,
+        long_press_time: default_long_press(),
+    }]
+}
+
+fn default_gpio_chip() -> String {
+    "/dev/gpiochip0".into()
+}
+
+fn default_gpio_debounce() -> f64 {
+    0.05
+}
+
+fn default_blink_interval() -> f64 {
+    0.5
+}
+
+fn default_open_timeout() -> f64 {
+    10.0
+}
+
+fn default_read_timeout() -> f64 {
+    5.0
+}
+
+fn default_keep_warm() -> f64 {
+    300.0
+}
+
+fn default_capture_timeout() -> f64 {
+    20.0
+}
+
+fn default_print_poll_interval() -> f64 {
+    2.0
+}
+
+fn default_max_copies() -> u32 {
+    4
+}
+
+fn default_copies() -> u32 {
+    1
+}
+
+fn default_quota_file() -> String {
+    "prints_made.txt".into()
+}
+
+fn default_review_timeout() -> f64 {
+    20.0
+}
+
+fn default_copies_timeout() -> f64 {
+    10.0
+}
+
+fn default_debounce() -> f64 {
+    0.25
+}
+
+fn default_swipe_distance() -> f64 {
+    0.2
+}
+
+fn default_long_press() -> f64 {
+    3.0
+}
+
+fn default_screen_timeout() -> f64 {
+    30.0
+}
+
+fn default_countdown() -> f64 {
+    4.0
+}
+
+fn default_debrief() -> f64 {
+    5.0
+}
+
+fn default_mirror() -> bool {
+    true
+}
+
+fn default_date_format() -> String {
+    "[month repr:long] [day padding:none], [year]".into()
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum ImageLayout {
     #[default]
     Single,
     TwoByTwo,
+    Grid(Grid),
+}
+
+/// Photos arranged in rows and columns, eg a 4x1 photo strip.
+///
+/// Spacing is given as a fraction of a photo's width, so the layout looks the same on screen as
+/// in the saved image.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Grid {
+    pub rows: u32,
+    pub columns: u32,
+    /// Space around the outside of the grid.
+    #[serde(default)]
+    pub margin: f64,
+    /// Space between neighbouring photos.
+    #[serde(default)]
+    pub gutter: f64,
+    /// Repeat the whole grid side by side, eg to print two strips on one sheet for cutting.
+    #[serde(default)]
+    pub duplicate: bool,
+    /// Colour of the margins and gutters, as red, green and blue.
+    #[serde(default = "default_grid_background")]
+    pub background: [u8; 3],
+}
+
+fn default_grid_background() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+/// Where one of the photos goes in a layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Slot {
+    /// Which photo of the session goes here, counting from 0. With `duplicate`, each photo
+    /// fills more than one slot.
+    pub shot: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 impl ImageLayout {
-    pub fn capture_count(&self) -> usize {
+    pub fn grid(&self) -> Grid {
+        let grid = |rows, columns| Grid {
+            rows,
+            columns,
+            margin: 0.0,
+            gutter: 0.0,
+            duplicate: false,
+            background: [0, 0, 0],
+        };
         match self {
-            Self::Single => 1,
-            Self::TwoByTwo => 4,
+            Self::Single => grid(1, 1),
+            Self::TwoByTwo => grid(2, 2),
+            Self::Grid(grid) => *grid,
         }
     }
 
-    pub fn dest_size<T>(&self, width: T, height: T) -> (T, T)
-    where
-        T: Copy + Mul<Output = T> + From<u8>,
-    {
-        match self {
-            Self::Single => (width, height),
-            Self::TwoByTwo => (T::from(2u8) * width, T::from(2u8) * height),
+    pub fn capture_count(&self) -> usize {
+        let grid = self.grid();
+        (grid.rows * grid.columns) as usize
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.grid().background
+    }
+
+    /// The size of the saved image for photos of the given size.
+    pub fn dest_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let grid = self.grid();
+        let (width_units, height_units) = grid.spacing_units();
+        let width = width as f64;
+        (
+            (grid.copies() as f64 * (width_units + grid.columns as f64) * width).round() as u32,
+            (height_units * width + grid.rows as f64 * height as f64).round() as u32,
+        )
+    }
+
+    /// Divides an image or screen area of the given size between the photos.
+    pub fn slots(&self, width: u32, height: u32) -> Vec<Slot> {
+        let grid = self.grid();
+        let (width_units, height_units) = grid.spacing_units();
+        let copy_width = width as f64 / grid.copies() as f64;
+        let photo_width = copy_width / (width_units + grid.columns as f64);
+        let photo_height =
+            ((height as f64 - height_units * photo_width) / grid.rows as f64).max(0.0);
+        let (margin, gutter) = (grid.margin * photo_width, grid.gutter * photo_width);
+
+        let mut slots = Vec::new();
+        for copy in 0..grid.copies() {
+            for row in 0..grid.rows {
+                for column in 0..grid.columns {
+                    let left =
+                        copy as f64 * copy_width + margin + column as f64 * (photo_width + gutter);
+                    let top = margin + row as f64 * (photo_height + gutter);
+                    let (x, y) = (left.round() as u32, top.round() as u32);
+                    slots.push(Slot {
+                        shot: (row * grid.columns + column) as usize,
+                        x,
+                        y,
+                        width: (left + photo_width).round() as u32 - x,
+                        height: (top + photo_height).round() as u32 - y,
+                    });
+                }
+            }
         }
+        slots
     }
 
-    pub fn arrange_within_rect<T>(&self, width: T, height: T) -> Vec<(T, T, T, T)>
-    where
-        T: Copy + Sub<Output = T> + Div<Output = T> + From<u8>,
-    {
-        let zero = 0u8.into();
-        match self {
-            Self::Single => vec![(zero, zero, width, height)],
-            Self::TwoByTwo => {
-                let w0 = width / 2u8.into();
-                let w1 = width - w0;
-                let h0 = height / 2u8.into();
-                let h1 = height - h0;
-                vec![
-                    (zero, zero, w0, h0),
-                    (w0, zero, w1, h0),
-                    (zero, h0, w0, h1),
-                    (w0, h0, w1, h1),
-                ]
+    pub fn validate(&self) -> Result<(), String> {
+        let grid = self.grid();
+        if grid.rows == 0 || grid.columns == 0 {
+            return Err("image.layout.grid needs at least one row and one column".into());
+        }
+        for (name, value) in [("margin", grid.margin), ("gutter", grid.gutter)] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!(
+                    "image.layout.grid.{name} must be a fraction of a photo's width, not {value}"
+                ));
             }
         }
+        Ok(())
+    }
+}
+
+impl Grid {
+    fn copies(&self) -> u32 {
+        if self.duplicate {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The space taken up by margins and gutters across one copy of the grid and down it, in
+    /// photo widths.
+    fn spacing_units(&self) -> (f64, f64) {
+        (
+            2.0 * self.margin + (self.columns - 1) as f64 * self.gutter,
+            2.0 * self.margin + (self.rows - 1) as f64 * self.gutter,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printer(toml: &str) -> PrinterSettings {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn job_options_follow_the_paper_settings() {
+        let options = printer(
+            r#"
+            media = "4x6"
+            borderless = true
+            "#,
+        )
+        .job_options();
+        assert_eq!(
+            options,
+            vec![
+                ("media".to_string(), "4x6".to_string()),
+                ("print-scaling".to_string(), "fill".to_string()),
+                ("Borderless".to_string(), "True".to_string()),
+            ]
+        );
+        assert!(printer("").job_options().is_empty());
+    }
+
+    #[test]
+    fn job_options_are_overridden_by_options() {
+        let options = printer(
+            r#"
+            media = "4x6"
+            borderless = true
+            options = { media = "5x7", print-quality = "5" }
+            "#,
+        )
+        .job_options();
+        assert_eq!(
+            options.iter().filter(|(name, _)| name == "media").count(),
+            1
+        );
+        assert!(options.contains(&("media".into(), "5x7".into())));
+        assert!(options.contains(&("print-quality".into(), "5".into())));
+        assert!(options.contains(&("Borderless".into(), "True".into())));
+    }
+
+    #[cfg(feature = "cups")]
+    #[test]
+    fn printing_with_both_the_printer_and_the_post_command_is_rejected() {
+        let config = |enable_post_command: bool| -> Config {
+            let config = format!(
+                r#"
+                [video_source]
+                path = "/dev/video0"
+                [image]
+                post_command = ["lpr"]
+                enable_post_command = {enable_post_command}
+                [printer]
+                "#
+            );
+            toml::from_str(&config).unwrap()
+        };
+        assert!(config(true).validate().is_err());
+        assert!(config(false).validate().is_ok());
+    }
+
+    #[test]
+    fn the_default_gpio_button_makes_way_for_an_output_on_its_line() {
+        let config = |gpio: &str| -> Config {
+            toml::from_str(&format!("[video_source]\npath = \"/dev/video0\"\n{gpio}")).unwrap()
+        };
+        let lines = |config: &Config| -> Vec<u32> {
+            config
+                .gpio_inputs()
+                .iter()
+                .map(|input| input.line)
+                .collect()
+        };
+        assert_eq!(lines(&config("")), [2]);
+        let light = config("[[gpio_outputs]]\nline = 17");
+        assert_eq!(lines(&light), [2]);
+        assert!(light.validate().is_ok());
+        let on_line_2 = config("[[gpio_outputs]]\nline = 2");
+        assert!(lines(&on_line_2).is_empty());
+        assert!(on_line_2.validate().is_ok());
+        let both = config("[[gpio]]\nline = 2\n[[gpio_outputs]]\nline = 2");
+        assert!(both.validate().unwrap_err().contains("line 2"));
+    }
+
+    #[test]
+    fn control_sockets_fall_back_to_the_temporary_directory() {
+        let control = ControlSettings {
+            socket: "booth.sock".into(),
+        };
+        let paths = control.socket_paths(Path::new("/media/usb/Rocketbooth.toml"));
+        assert_eq!(paths[0], Path::new("/media/usb/booth.sock"));
+        assert_eq!(paths.last(), Some(&std::env::temp_dir().join("booth.sock")));
+    }
+
+    #[test]
+    fn the_old_gpio_bindings_are_turned_away() {
+        let controls: Controls = toml::from_str(
+            r#"
+            [bindings]
+            gpio = [{ line = 2, action = "advance" }]
+            "#,
+        )
+        .unwrap();
+        let error = controls.bindings.validate().unwrap_err();
+        assert!(error.contains("[[gpio]]"), "{error}");
+        let controls: Controls = toml::from_str("").unwrap();
+        assert!(controls.bindings.validate().is_ok());
     }
 }
\ No newline at end of file
@@ -0,0 +1,335 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// How long a connection waits for the booth to carry out a command, which it does between
+/// frames unless it's busy taking a photo.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command sent to the control socket as a line of JSON, eg `{"command": "status"}`.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Just reply with how the booth is doing.
+    Status,
+    /// Start a session's countdown straight away, from the idle, welcome or explainer screens.
+    Trigger,
+    /// Stay on the idle screen, ignoring guests, once any session in progress is over.
+    Pause,
+    Resume,
+    /// Print the last session saved again, as long as a `[printer]` is set up and the copies
+    /// are within the `[copies]` limits.
+    Reprint {
+        #[serde(default = "default_reprint_copies")]
+        copies: u32,
+    },
+    /// Read the config file and theme again, while the booth is idle. The GPIO pins, serial
+    /// port, control socket and display rotation stay as they were until it's restarted.
+    Reload,
+    /// Exit rocketbooth, whether or not `enable_exit_inputs` is set.
+    Shutdown,
+}
+
+fn default_reprint_copies() -> u32 {
+    1
+}
+
+impl ControlCommand {
+    /// The command's name, as it's sent.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlCommand::Status => "status",
+            ControlCommand::Trigger => "trigger",
+            ControlCommand::Pause => "pause",
+            ControlCommand::Resume => "resume",
+            ControlCommand::Reprint { .. } => "reprint",
+            ControlCommand::Reload => "reload",
+            ControlCommand::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// How the booth is doing, as sent back after each command.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The screen being shown, named as in the serial controller's `STATE` messages.
+    pub state: String,
+    /// The photo of the session being counted down to, counting from 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shot: Option<usize>,
+    pub paused: bool,
+    /// The number of the next session to be saved.
+    pub session_number: u32,
+    /// Prints made towards the event's quota.
+    pub prints_made: u32,
+    pub printer_problems: Vec<String>,
+}
+
+/// The answer to a command, sent back as a line of JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Reply {
+    pub ok: bool,
+    /// Why the command wasn't carried out, if it wasn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How the booth is doing after carrying out the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+impl Reply {
+    pub fn done(status: Status) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            status: Some(status),
+        }
+    }
+
+    pub fn failed(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.into()),
+            status: None,
+        }
+    }
+}
+
+/// A command from the control socket, sent to the booth's event loop as an SDL custom event.
+/// It has to be registered with `EventSubsystem::register_custom_event` before it's sent.
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<Reply>,
+}
+
+impl ControlRequest {
+    /// Sends the answer back to the connection the command came from.
+    pub fn reply(&self, reply: Reply) {
+        // The connection only goes away early if it gave up waiting.
+        let _ = self.reply.send(reply);
+    }
+}
+
+/// Listens for connections to the control socket on the first of `paths` it can, as given by
+/// `ControlSettings::socket_paths`, returning where it's listening. The paths it can't listen
+/// on are reported, along with where it's listening instead.
+pub fn bind_control_socket(paths: &[PathBuf]) -> crate::Result<(UnixListener, PathBuf)> {
+    for (i, path) in paths.iter().enumerate() {
+        match bind(path) {
+            Ok(listener) => {
+                if i > 0 {
+                    eprintln!("Listening for control commands on {}", path.display());
+                }
+                return Ok((listener, path.clone()));
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    Err("Nowhere to listen for control commands".into())
+}
+
+/// Listens on `path`, replacing the socket left behind by a booth that didn't shut down
+/// cleanly.
+fn bind(path: &Path) -> crate::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("Another booth is already listening on {}", path.display()).into());
+        }
+        std::fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove the old {}: {e}", path.display()))?;
+    }
+    Ok(UnixListener::bind(path)
+        .map_err(|e| format!("Failed to listen on {}: {e}", path.display()))?)
+}
+
+/// Answers connections to the control socket until it fails, each on its own thread. The
+/// commands read from them are passed to `send`, which gets them to the booth.
+pub fn serve_control(
+    listener: UnixListener,
+    send: impl Fn(ControlRequest) -> crate::Result<()> + Send + Sync + 'static,
+) -> crate::Result<()> {
+    let send = Arc::new(send);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let send = Arc::clone(&send);
+        std::thread::spawn(move || {
+            if let Err(e) = answer(stream, &*send) {
+                eprintln!("Control connection closed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Reads commands from a connection a line at a time, writing a reply to each.
+fn answer(
+    stream: UnixStream,
+    send: &dyn Fn(ControlRequest) -> crate::Result<()>,
+) -> crate::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(command) => {
+                let (reply, replied) = channel();
+                send(ControlRequest { command, reply })?;
+                replied
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Reply::failed("The booth didn't answer in time"))
+            }
+            Err(e) => Reply::failed(format!("Not a command: {e}")),
+        };
+        serde_json::to_writer(&mut writer, &reply)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Sends `command` to the booth listening on the first of `paths` that's answered, as given by
+/// `ControlSettings::socket_paths`, returning its reply.
+pub fn send_command(paths: &[PathBuf], command: ControlCommand) -> crate::Result<Reply> {
+    let mut stream = (paths.iter())
+        .find_map(|path| UnixStream::connect(path).ok())
+        .ok_or_else(|| format!("No booth is listening on {paths:?}"))?;
+    serde_json::to_writer(&mut stream, &command)?;
+    stream.write_all(b"\n")?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match line.trim() {
+        // The booth can exit before the reply is written.
+        "" if command == ControlCommand::Shutdown => Ok(Reply {
+            ok: true,
+            error: None,
+            status: None,
+        }),
+        "" => Err("The booth closed the connection without replying".into()),
+        line => Ok(serde_json::from_str(line)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Receiver;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let name = format!("rocketbooth-test-{}-{name}.sock", std::process::id());
+        std::env::temp_dir().join(name)
+    }
+
+    /// Serves a new socket, returning its path and the commands that come through it. They're
+    /// answered in place of the booth, failing reprints and otherwise reporting the command's
+    /// name as the booth's state; carrying them out is left to `run_command`'s tests.
+    fn booth(name: &str) -> (PathBuf, Receiver<ControlCommand>) {
+        let (listener, path) = bind_control_socket(&[socket_path(name)]).unwrap();
+        let (sender, commands) = channel();
+        std::thread::spawn(move || {
+            let send = move |request: ControlRequest| {
+                sender.send(request.command)?;
+                request.reply(match request.command {
+                    ControlCommand::Reprint { copies } => Reply::failed(format!("{copies} copies")),
+                    command => Reply::done(Status {
+                        state: command.name().into(),
+                        shot: None,
+                        paused: false,
+                        session_number: 1,
+                        prints_made: 0,
+                        printer_problems: vec![],
+                    }),
+                });
+                Ok(())
+            };
+            serve_control(listener, send).unwrap();
+        });
+        (path, commands)
+    }
+
+    #[test]
+    fn commands_and_replies_make_the_round_trip() {
+        let (path, commands) = booth("round-trip");
+        for command in [
+            ControlCommand::Status,
+            ControlCommand::Trigger,
+            ControlCommand::Pause,
+            ControlCommand::Resume,
+            ControlCommand::Reload,
+        ] {
+            let reply = send_command(std::slice::from_ref(&path), command).unwrap();
+            assert!(reply.ok && reply.error.is_none());
+            assert_eq!(reply.status.unwrap().state, command.name());
+            assert_eq!(commands.recv().unwrap(), command);
+        }
+        let reply = send_command(
+            std::slice::from_ref(&path),
+            ControlCommand::Reprint { copies: 3 },
+        )
+        .unwrap();
+        assert_eq!(
+            (reply.ok, reply.error, reply.status),
+            (false, Some("3 copies".into()), None)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn each_line_of_a_connection_is_answered() {
+        let (path, commands) = booth("lines");
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(b"{\"command\": \"pause\"}\n\nnot json\n{\"command\": \"reprint\"}\n")
+            .unwrap();
+        let mut replies = BufReader::new(stream).lines().map(|line| {
+            let reply: Reply = serde_json::from_str(&line.unwrap()).unwrap();
+            (reply.ok, reply.error)
+        });
+        assert_eq!(replies.next().unwrap(), (true, None));
+        let (ok, error) = replies.next().unwrap();
+        assert!(!ok && error.unwrap().starts_with("Not a command"));
+        // Reprints are of a single copy unless they say otherwise.
+        assert_eq!(replies.next().unwrap(), (false, Some("1 copies".into())));
+        let sent: Vec<_> = commands.try_iter().collect();
+        assert_eq!(
+            sent,
+            [ControlCommand::Pause, ControlCommand::Reprint { copies: 1 }]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sockets_fall_back_to_the_next_path_they_can_listen_on() {
+        let missing = std::env::temp_dir().join("rocketbooth-missing/rocketbooth.sock");
+        let paths = [missing, socket_path("fallback")];
+        let (listener, path) = bind_control_socket(&paths).unwrap();
+        assert_eq!(path, paths[1]);
+        // A booth that's still listening isn't replaced, but its socket is once it's gone.
+        assert!(bind_control_socket(&paths[1..]).is_err());
+        drop(listener);
+        let (_listener, path) = bind_control_socket(&paths[1..]).unwrap();
+        assert_eq!(path, paths[1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn commands_are_sent_to_the_first_path_listening() {
+        let (path, _commands) = booth("send");
+        let paths = [socket_path("nobody"), path.clone()];
+        assert!(send_command(&paths, ControlCommand::Status).unwrap().ok);
+        assert!(send_command(&paths[..1], ControlCommand::Status).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+    render::{Canvas, RenderTarget, Texture},
+};
+
+use crate::framing::rotated_size;
+
+/// The screen the booth draws on, which is turned into place on the window to suit a display
+/// mounted on its side.
+#[derive(Copy, Clone, Debug)]
+pub struct Screen {
+    window_width: u32,
+    window_height: u32,
+    /// Clockwise turn from the screen to the window, in degrees.
+    rotation: u32,
+}
+
+impl Screen {
+    pub fn new(window_size: (u32, u32), rotation: u32) -> Self {
+        let (window_width, window_height) = window_size;
+        Self {
+            window_width,
+            window_height,
+            rotation,
+        }
+    }
+
+    /// The size of the window the screen is shown in.
+    pub fn window_size(&self) -> (u32, u32) {
+        (self.window_width, self.window_height)
+    }
+
+    /// The size the booth draws at, before it's turned into place.
+    pub fn size(&self) -> (u32, u32) {
+        rotated_size(self.window_width, self.window_height, self.rotation)
+    }
+
+    /// Whether the screen has to be drawn off the window and then turned.
+    pub fn is_rotated(&self) -> bool {
+        self.rotation != 0
+    }
+
+    /// Maps a point on the window, eg where it was touched, to the screen the booth draws.
+    pub fn to_screen(&self, point: Point) -> Point {
+        let (x, y) = (point.x(), point.y());
+        let (width, height) = (self.window_width as i32, self.window_height as i32);
+        match self.rotation {
+            90 => Point::new(y, width - 1 - x),
+            180 => Point::new(width - 1 - x, height - 1 - y),
+            270 => Point::new(height - 1 - y, x),
+            _ => point,
+        }
+    }
+
+    /// Maps a touch, given in fractions of the window's width and height as SDL reports them,
+    /// to the screen the booth draws.
+    pub fn touch_to_screen(&self, x: f32, y: f32) -> Point {
+        let x = (x * self.window_width as f32) as i32;
+        let y = (y * self.window_height as f32) as i32;
+        self.to_screen(Point::new(
+            x.clamp(0, self.window_width as i32 - 1),
+            y.clamp(0, self.window_height as i32 - 1),
+        ))
+    }
+
+    /// Draws `screen`, a texture the booth has drawn on, turned into place over the window.
+    pub fn draw_rotated<U>(&self, canvas: &mut Canvas<U>, screen: &Texture) -> crate::Result<()>
+    where
+        U: RenderTarget,
+    {
+        let (width, height) = self.size();
+        let window = Rect::new(0, 0, self.window_width, self.window_height);
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.copy_ex(
+            screen,
+            None,
+            Rect::from_center(window.center(), width, height),
+            self.rotation as f64,
+            None,
+            false,
+            false,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use image::{imageops, Rgb, RgbImage};
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{Canvas, RenderTarget, Texture},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::theme::{place, Anchor, Scale};
+
+/// How each camera frame is cut down to the photo that's shown and saved, so the preview, the
+/// thumbnails and the saved image all show the same part of the scene.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default)]
+pub struct Framing {
+    /// The shape of the photos as width divided by height, eg 1.5 for 6x4 prints. Without one,
+    /// photos keep the shape of the crop region.
+    pub aspect: Option<f64>,
+    /// How a frame of a different shape is made to match `aspect`.
+    #[serde(default)]
+    pub fit: FitMode,
+    /// Colour of the bars added with `FitMode::Fit`, as red, green and blue.
+    #[serde(default)]
+    pub fill_color: [u8; 3],
+    /// The part of the frame to use, before `aspect` is applied. Without one, the whole frame
+    /// is used.
+    pub crop: Option<CropRegion>,
+}
+
+/// How a frame is made to match the photos' shape.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FitMode {
+    /// Cut off the edges that don't fit, keeping the middle of the frame.
+    #[default]
+    Crop,
+    /// Keep the whole frame, adding bars of `fill_color` to either side.
+    Fit,
+}
+
+/// A rectangle within the camera frame, in fractions of its width and height, so it picks out the
+/// same part of the scene whatever the resolution.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct CropRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Framing {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(aspect) = self.aspect {
+            if !(aspect.is_finite() && aspect > 0.0) {
+                return Err(format!(
+                    "image.framing.aspect must be the photos' width divided by their height, not {aspect}"
+                ));
+            }
+        }
+        if let Some(crop) = &self.crop {
+            let values = [crop.x, crop.y, crop.width, crop.height];
+            if values
+                .iter()
+                .any(|value| !(value.is_finite() && *value >= 0.0))
+                || crop.width == 0.0
+                || crop.height == 0.0
+                || crop.x + crop.width > 1.0
+                || crop.y + crop.height > 1.0
+            {
+                return Err(format!(
+                    "image.framing.crop must lie within the frame, in fractions of its size from 0 to 1, not {crop:?}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The part of a frame of the given size that makes up the photo.
+    pub fn region(&self, width: u32, height: u32) -> Rect {
+        let (width, height) = (width as f64, height as f64);
+        let (x, y, region_width, region_height) = match &self.crop {
+            Some(crop) => (
+                crop.x * width,
+                crop.y * height,
+                crop.width * width,
+                crop.height * height,
+            ),
+            None => (0.0, 0.0, width, height),
+        };
+        let (x, y) = (x.round() as u32, y.round() as u32);
+        let region = Rect::new(
+            x as i32,
+            y as i32,
+            ((x as f64 + region_width).round() as u32)
+                .saturating_sub(x)
+                .max(1),
+            ((y as f64 + region_height).round() as u32)
+                .saturating_sub(y)
+                .max(1),
+        );
+        match (self.aspect, self.fit) {
+            (Some(aspect), FitMode::Crop) => {
+                let (width, height) = shape(region.width(), region.height(), aspect, f64::min);
+                place(width, height, Scale::Original, Anchor::Center, region)
+            }
+            _ => region,
+        }
+    }
+
+    /// The size of the photo made from a frame of the given size.
+    pub fn framed_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let region = self.region(width, height);
+        match (self.aspect, self.fit) {
+            (Some(aspect), FitMode::Fit) => {
+                shape(region.width(), region.height(), aspect, f64::max)
+            }
+            _ => (region.width(), region.height()),
+        }
+    }
+
+    /// Makes the photo to be saved from a captured frame, first turning it clockwise by
+    /// `rotation` degrees, and finally mirroring it if `mirror` is set.
+    pub fn apply(&self, frame: &RgbImage, rotation: u32, mirror: bool) -> RgbImage {
+        let rotated;
+        let frame = match rotation {
+            90 => {
+                rotated = imageops::rotate90(frame);
+                &rotated
+            }
+            180 => {
+                rotated = imageops::rotate180(frame);
+                &rotated
+            }
+            270 => {
+                rotated = imageops::rotate270(frame);
+                &rotated
+            }
+            _ => frame,
+        };
+        let mut photo = self.frame(frame);
+        if mirror {
+            imageops::flip_horizontal_in_place(&mut photo);
+        }
+        photo
+    }
+
+    fn frame(&self, frame: &RgbImage) -> RgbImage {
+        let region = self.region(frame.width(), frame.height());
+        let photo = imageops::crop_imm(
+            frame,
+            region.x() as u32,
+            region.y() as u32,
+            region.width(),
+            region.height(),
+        )
+        .to_image();
+        let (width, height) = self.framed_size(frame.width(), frame.height());
+        if photo.dimensions() == (width, height) {
+            return photo;
+        }
+        let mut framed = RgbImage::from_pixel(width, height, Rgb(self.fill_color));
+        imageops::overlay(
+            &mut framed,
+            &photo,
+            (width - photo.width()) as i64 / 2,
+            (height - photo.height()) as i64 / 2,
+        );
+        framed
+    }
+
+    /// Draws the photo made from the frame in `texture` over `dest`, turned clockwise by
+    /// `rotation` degrees and mirrored if `mirror` is set, as `apply` does.
+    pub fn render<U>(
+        &self,
+        canvas: &mut Canvas<U>,
+        texture: &Texture,
+        dest: Rect,
+        rotation: u32,
+        mirror: bool,
+    ) -> crate::Result<()>
+    where
+        U: RenderTarget,
+    {
+        let query = texture.query();
+        let (width, height) = rotated_size(query.width, query.height, rotation);
+        let region = self.region(width, height);
+        let dest = match (self.aspect, self.fit) {
+            (Some(_), FitMode::Fit) => {
+                let [r, g, b] = self.fill_color;
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas.fill_rect(dest)?;
+                canvas.set_draw_color(Color::BLACK);
+                place(
+                    region.width(),
+                    region.height(),
+                    Scale::Fit,
+                    Anchor::Center,
+                    dest,
+                )
+            }
+            _ => dest,
+        };
+
+        // SDL crops in the texture's own orientation and turns the result about the centre of
+        // its destination, mirroring before it turns.
+        let (w, h) = (query.width as i32, query.height as i32);
+        let (source, dest, flip_horizontal, flip_vertical) = match rotation {
+            90 => (
+                Rect::new(
+                    region.y(),
+                    h - region.right(),
+                    region.height(),
+                    region.width(),
+                ),
+                Rect::from_center(dest.center(), dest.height(), dest.width()),
+                false,
+                mirror,
+            ),
+            180 => (
+                Rect::new(
+                    w - region.right(),
+                    h - region.bottom(),
+                    region.width(),
+                    region.height(),
+                ),
+                dest,
+                mirror,
+                false,
+            ),
+            270 => (
+                Rect::new(
+                    w - region.bottom(),
+                    region.x(),
+                    region.height(),
+                    region.width(),
+                ),
+                Rect::from_center(dest.center(), dest.height(), dest.width()),
+                false,
+                mirror,
+            ),
+            _ => (region, dest, mirror, false),
+        };
+        canvas.copy_ex(
+            texture,
+            source,
+            dest,
+            rotation as f64,
+            None,
+            flip_horizontal,
+            flip_vertical,
+        )?;
+        Ok(())
+    }
+}
+
+/// The size of a frame of the given size once it's turned by `rotation` degrees.
+pub fn rotated_size(width: u32, height: u32, rotation: u32) -> (u32, u32) {
+    match rotation {
+        90 | 270 => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// The size with the given aspect ratio that fits inside (with `f64::min`) or covers (with
+/// `f64::max`) an area of the given size.
+fn shape(width: u32, height: u32, aspect: f64, pick: fn(f64, f64) -> f64) -> (u32, u32) {
+    let (width, height) = (width as f64, height as f64);
+    let scale = pick(width / aspect, height);
+    (
+        ((scale * aspect).round() as u32).max(1),
+        (scale.round() as u32).max(1),
+    )
+}
@@ -0,0 +1,535 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{Action, Edge, GpioInput, GpioOutput, Phase};
+
+/// The GPIO lines: the kernel's GPIO chips with the `gpio` feature, or a mock in the tests.
+pub trait GpioBackend {
+    /// Starts watching an input's line for changes.
+    fn watch(&mut self, input: &GpioInput) -> crate::Result<()>;
+
+    /// Waits up to `timeout` for a watched line to change, returning which one, counting in the
+    /// order they were watched, and the way it changed.
+    fn wait(&mut self, timeout: Duration) -> crate::Result<Option<(usize, Edge)>>;
+
+    /// The time by the backend's clock, which long presses are timed against.
+    fn now(&self) -> Instant;
+
+    /// Takes over an output's line, starting off.
+    fn drive(&mut self, output: &GpioOutput) -> crate::Result<()>;
+
+    /// Turns a driven line on or off, counting in the order they were driven.
+    fn set(&mut self, output: usize, on: bool) -> crate::Result<()>;
+}
+
+impl<B: GpioBackend + ?Sized> GpioBackend for Box<B> {
+    fn watch(&mut self, input: &GpioInput) -> crate::Result<()> {
+        (**self).watch(input)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> crate::Result<Option<(usize, Edge)>> {
+        (**self).wait(timeout)
+    }
+
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn drive(&mut self, output: &GpioOutput) -> crate::Result<()> {
+        (**self).drive(output)
+    }
+
+    fn set(&mut self, output: usize, on: bool) -> crate::Result<()> {
+        (**self).set(output, on)
+    }
+}
+
+/// Turns the changes on the GPIO lines into what the buttons on them do.
+pub struct GpioButtons {
+    inputs: Vec<GpioInput>,
+    buttons: Vec<ButtonState>,
+}
+
+#[derive(Default)]
+struct ButtonState {
+    pressed_since: Option<Instant>,
+    last_change: Option<Instant>,
+    long_pressed: bool,
+    /// Whether the line was last seen pressed, including while it was bouncing.
+    level: bool,
+}
+
+impl GpioButtons {
+    /// Starts watching the inputs' lines.
+    pub fn open(backend: &mut impl GpioBackend, inputs: Vec<GpioInput>) -> crate::Result<Self> {
+        for input in &inputs {
+            backend.watch(input)?;
+        }
+        let buttons = inputs.iter().map(|_| ButtonState::default()).collect();
+        Ok(Self { inputs, buttons })
+    }
+
+    /// Waits up to `timeout` for a line to change, returning what the buttons did meanwhile.
+    /// Call it again straight away, so long presses are noticed on time.
+    pub fn read(
+        &mut self,
+        backend: &mut impl GpioBackend,
+        timeout: Duration,
+    ) -> crate::Result<Vec<Action>> {
+        let change = backend.wait(timeout)?;
+        let now = backend.now();
+        let mut actions = vec![];
+        if let Some((i, edge)) = change {
+            self.buttons[i].level = edge == self.inputs[i].edge;
+        }
+        for (button, input) in self.buttons.iter_mut().zip(&self.inputs) {
+            actions.extend(button.settle(input, now));
+            actions.extend(button.hold(input, now));
+        }
+        Ok(actions)
+    }
+}
+
+impl ButtonState {
+    /// What the button does once its line has settled at a new level. Changes while it's
+    /// bouncing wait until the debounce time is up, so a tap shorter than that is still let go.
+    fn settle(&mut self, input: &GpioInput, now: Instant) -> Option<Action> {
+        let bouncing = (self.last_change).is_some_and(|last| now < last + seconds(input.debounce));
+        let pressed = self.level;
+        if bouncing || pressed == self.pressed_since.is_some() {
+            return None;
+        }
+        self.last_change = Some(now);
+        if pressed {
+            self.pressed_since = Some(now);
+            self.long_pressed = false;
+            input.long_press.is_none().then_some(input.press)
+        } else {
+            self.pressed_since = None;
+            (input.long_press.is_some() && !self.long_pressed).then_some(input.press)
+        }
+    }
+
+    /// The long press, once the button has been held down for long enough.
+    fn hold(&mut self, input: &GpioInput, now: Instant) -> Option<Action> {
+        let since = self.pressed_since?;
+        let long_press = (input.long_press)
+            .filter(|_| !self.long_pressed && now >= since + seconds(input.long_press_time))?;
+        self.long_pressed = true;
+        Some(long_press)
+    }
+}
+
+/// Drives the GPIO outputs as the booth moves through a session.
+pub struct GpioOutputs<B = Box<dyn GpioBackend>> {
+    backend: B,
+    outputs: Vec<GpioOutput>,
+    levels: Vec<bool>,
+    /// When each output's flash ends, while it's flashing.
+    flashes: Vec<Option<Instant>>,
+    phase: Option<(Phase, Instant)>,
+}
+
+impl<B: GpioBackend> GpioOutputs<B> {
+    /// Takes over the outputs' lines, turning them all off.
+    pub fn open(mut backend: B, outputs: Vec<GpioOutput>) -> crate::Result<Self> {
+        for output in &outputs {
+            backend.drive(output)?;
+        }
+        Ok(Self {
+            backend,
+            levels: vec![false; outputs.len()],
+            flashes: vec![None; outputs.len()],
+            outputs,
+            phase: None,
+        })
+    }
+
+    /// Sets the lines for the booth's current phase. Call it on every frame, so the lines
+    /// blink, and flashes end, on time.
+    pub fn update(&mut self, phase: Phase, now: Instant) -> crate::Result<()> {
+        let since = match self.phase {
+            Some((current, since)) if current == phase => since,
+            _ => {
+                self.phase = Some((phase, now));
+                now
+            }
+        };
+        for (i, output) in self.outputs.iter().enumerate() {
+            let flashing = self.flashes[i].is_some_and(|end| now < end);
+            let blink_on = || {
+                let interval = (now - since).as_secs_f64() / output.blink_interval;
+                (interval as u64).is_multiple_of(2)
+            };
+            let on = flashing
+                || output.on.contains(&phase)
+                || (output.blink.contains(&phase) && blink_on());
+            if on != self.levels[i] {
+                self.backend.set(i, on)?;
+                self.levels[i] = on;
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns on the flash lines, as a photo is about to be taken. They're turned off by
+    /// `update` once their time is up.
+    pub fn flash(&mut self, now: Instant) -> crate::Result<()> {
+        for (i, output) in self.outputs.iter().enumerate() {
+            if output.flash > 0.0 {
+                self.flashes[i] = Some(now + seconds(output.flash));
+                if !self.levels[i] {
+                    self.backend.set(i, true)?;
+                    self.levels[i] = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the lines of the kernel's GPIO chips, eg `/dev/gpiochip0`.
+#[cfg(feature = "gpio")]
+#[derive(Default)]
+pub struct ChipGpio {
+    chips: std::collections::HashMap<String, gpiochip::GpioChip>,
+    lines: Vec<gpiochip::GpioEventHandle>,
+    outputs: Vec<gpiochip::GpioHandle>,
+}
+
+#[cfg(feature = "gpio")]
+impl ChipGpio {
+    fn chip(&mut self, path: &str) -> crate::Result<&gpiochip::GpioChip> {
+        use std::collections::hash_map::Entry;
+
+        Ok(match self.chips.entry(path.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                gpiochip::GpioChip::new(path).map_err(|e| format!("Failed to open {path}: {e}"))?,
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl GpioBackend for ChipGpio {
+    fn watch(&mut self, input: &GpioInput) -> crate::Result<()> {
+        use crate::config::Bias;
+        use gpiochip::{EventRequestFlags, RequestFlags};
+
+        let chip = self.chip(&input.chip)?;
+        // The kernel's bias flags, which gpiochip doesn't name.
+        let bias = match input.bias {
+            Bias::AsIs => 0,
+            Bias::PullUp => 1 << 5,
+            Bias::PullDown => 1 << 6,
+            Bias::Disabled => 1 << 7,
+        };
+        // Safe, as the flags are passed straight to the kernel, which checks them.
+        let flags = unsafe { RequestFlags::from_bits_unchecked(RequestFlags::INPUT.bits() | bias) };
+        let line = chip
+            .request_event(
+                "rocketbooth",
+                input.line,
+                flags,
+                EventRequestFlags::BOTH_EDGES,
+            )
+            .map_err(|e| format!("Failed to watch line {} on {}: {e}", input.line, input.chip))?;
+        self.lines.push(line);
+        Ok(())
+    }
+
+    fn wait(&mut self, timeout: Duration) -> crate::Result<Option<(usize, Edge)>> {
+        if self.lines.is_empty() {
+            std::thread::sleep(timeout);
+            return Ok(None);
+        }
+        let lines: Vec<_> = self.lines.iter().collect();
+        let ready = gpiochip::wait_for_event(&lines, timeout.as_millis() as i32)?;
+        let Some(i) = (0..lines.len()).find(|i| ready & (1 << i) != 0) else {
+            return Ok(None);
+        };
+        let edge = match lines[i].read()?.id {
+            gpiochip::EventId::RISING_EDGE => Edge::Rising,
+            gpiochip::EventId::FALLING_EDGE => Edge::Falling,
+        };
+        Ok(Some((i, edge)))
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn drive(&mut self, output: &GpioOutput) -> crate::Result<()> {
+        use gpiochip::RequestFlags;
+
+        let flags = if output.active_low {
+            RequestFlags::OUTPUT | RequestFlags::ACTIVE_LOW
+        } else {
+            RequestFlags::OUTPUT
+        };
+        let line = (self.chip(&output.chip)?)
+            .request("rocketbooth", flags, output.line, 0)
+            .map_err(|e| {
+                format!(
+                    "Failed to drive line {} on {}: {e}",
+                    output.line, output.chip
+                )
+            })?;
+        self.outputs.push(line);
+        Ok(())
+    }
+
+    fn set(&mut self, output: usize, on: bool) -> crate::Result<()> {
+        Ok(self.outputs[output].set(on as u8)?)
+    }
+}
+
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// A `GpioBackend` playing back a script of changes, on a clock of its own that only moves
+    /// while it's waited on, and keeping track of the lines it's told to drive.
+    struct MockGpio {
+        now: Instant,
+        watched: Vec<GpioInput>,
+        script: VecDeque<(Duration, u32, Edge)>,
+        driven: Vec<(GpioOutput, bool)>,
+    }
+
+    impl MockGpio {
+        fn new() -> Self {
+            Self {
+                now: Instant::now(),
+                watched: vec![],
+                script: VecDeque::new(),
+                driven: vec![],
+            }
+        }
+
+        /// Adds a change on `line`, `after` the one before it.
+        fn change(mut self, after: Duration, line: u32, edge: Edge) -> Self {
+            self.script.push_back((after, line, edge));
+            self
+        }
+
+        /// Whether a driven line is on, or `None` if it isn't being driven.
+        fn level(&self, line: u32) -> Option<bool> {
+            (self.driven.iter())
+                .find(|(output, _)| output.line == line)
+                .map(|(_, on)| *on)
+        }
+    }
+
+    impl GpioBackend for MockGpio {
+        fn watch(&mut self, input: &GpioInput) -> crate::Result<()> {
+            self.watched.push(input.clone());
+            Ok(())
+        }
+
+        fn wait(&mut self, timeout: Duration) -> crate::Result<Option<(usize, Edge)>> {
+            let Some(&(after, line, edge)) = self.script.front() else {
+                self.now += timeout;
+                return Ok(None);
+            };
+            if after > timeout {
+                self.now += timeout;
+                self.script[0].0 = after - timeout;
+                return Ok(None);
+            }
+            self.now += after;
+            self.script.pop_front();
+            let i = (self.watched.iter())
+                .position(|input| input.line == line)
+                .ok_or_else(|| format!("Line {line} isn't being watched"))?;
+            Ok(Some((i, edge)))
+        }
+
+        fn now(&self) -> Instant {
+            self.now
+        }
+
+        fn drive(&mut self, output: &GpioOutput) -> crate::Result<()> {
+            self.driven.push((output.clone(), false));
+            Ok(())
+        }
+
+        fn set(&mut self, output: usize, on: bool) -> crate::Result<()> {
+            let (_, level) = (self.driven.get_mut(output)).ok_or("No such output")?;
+            *level = on;
+            Ok(())
+        }
+    }
+
+    fn input(toml: &str) -> GpioInput {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Reads the buttons for `time`, returning what they did and when, in milliseconds.
+    fn play(mut mock: MockGpio, inputs: Vec<GpioInput>, time: Duration) -> Vec<(u128, Action)> {
+        let start = mock.now();
+        let mut buttons = GpioButtons::open(&mut mock, inputs).unwrap();
+        let mut actions = vec![];
+        while mock.now() - start < time {
+            for action in buttons.read(&mut mock, ms(10)).unwrap() {
+                actions.push(((mock.now() - start).as_millis(), action));
+            }
+        }
+        actions
+    }
+
+    #[test]
+    fn bouncing_contacts_press_once() {
+        let mock = MockGpio::new()
+            .change(ms(100), 2, Edge::Rising)
+            .change(ms(3), 2, Edge::Falling)
+            .change(ms(3), 2, Edge::Rising)
+            .change(ms(200), 2, Edge::Falling);
+        let actions = play(mock, vec![input("line = 2")], ms(1000));
+        assert_eq!(actions, vec![(100, Action::Advance)]);
+    }
+
+    #[test]
+    fn falling_edge_buttons_press_as_the_line_falls() {
+        let mock =
+            MockGpio::new()
+                .change(ms(100), 2, Edge::Falling)
+                .change(ms(200), 2, Edge::Rising);
+        let actions = play(mock, vec![input("line = 2\nedge = \"falling\"")], ms(1000));
+        assert_eq!(actions, vec![(100, Action::Advance)]);
+    }
+
+    #[test]
+    fn holding_a_button_long_presses_it_without_a_press_on_release() {
+        let mock =
+            MockGpio::new()
+                .change(ms(100), 2, Edge::Rising)
+                .change(ms(3500), 2, Edge::Falling);
+        let inputs = vec![input("line = 2\nlong_press = \"shutdown\"")];
+        let actions = play(mock, inputs, ms(5000));
+        assert_eq!(actions, vec![(3100, Action::Shutdown)]);
+    }
+
+    #[test]
+    fn presses_act_on_release_when_the_button_can_be_held() {
+        let mock =
+            MockGpio::new()
+                .change(ms(100), 2, Edge::Rising)
+                .change(ms(500), 2, Edge::Falling);
+        let inputs = vec![input("line = 2\nlong_press = \"shutdown\"")];
+        let actions = play(mock, inputs, ms(5000));
+        assert_eq!(actions, vec![(600, Action::Advance)]);
+    }
+
+    #[test]
+    fn taps_shorter_than_the_debounce_time_are_let_go() {
+        let mock =
+            MockGpio::new()
+                .change(ms(100), 2, Edge::Rising)
+                .change(ms(10), 2, Edge::Falling);
+        let inputs = vec![input("line = 2\nlong_press = \"shutdown\"")];
+        let actions = play(mock, inputs, ms(5000));
+        assert_eq!(actions, vec![(150, Action::Advance)]);
+    }
+
+    #[test]
+    fn buttons_on_different_lines_are_told_apart() {
+        let mock = MockGpio::new()
+            .change(ms(100), 3, Edge::Rising)
+            .change(ms(100), 3, Edge::Falling)
+            .change(ms(100), 2, Edge::Rising)
+            .change(ms(100), 2, Edge::Falling);
+        let inputs = vec![input("line = 2"), input("line = 3\npress = \"cancel\"")];
+        let actions = play(mock, inputs, ms(1000));
+        assert_eq!(actions, vec![(100, Action::Cancel), (300, Action::Advance)]);
+    }
+
+    fn output(toml: &str) -> GpioOutput {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// The level of each line after updating the outputs for `phase` at each time, in
+    /// milliseconds from `start`.
+    fn levels(
+        outputs: &mut GpioOutputs<MockGpio>,
+        line: u32,
+        phase: Phase,
+        start: Instant,
+        times: &[u64],
+    ) -> Vec<bool> {
+        (times.iter())
+            .map(|time| {
+                outputs.update(phase, start + ms(*time)).unwrap();
+                outputs.backend.level(line).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outputs_start_off_and_follow_the_phases_they_are_on_for() {
+        let on = output("line = 17\non = [\"idle\", \"welcome\"]");
+        let mut outputs = GpioOutputs::open(MockGpio::new(), vec![on]).unwrap();
+        assert_eq!(outputs.backend.level(17), Some(false));
+        let start = Instant::now();
+        assert_eq!(levels(&mut outputs, 17, Phase::Idle, start, &[0]), [true]);
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Welcome, start, &[100]),
+            [true]
+        );
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Countdown, start, &[200]),
+            [false]
+        );
+        assert_eq!(levels(&mut outputs, 17, Phase::Idle, start, &[300]), [true]);
+    }
+
+    #[test]
+    fn outputs_blink_from_the_start_of_their_phase() {
+        let blink = output("line = 17\nblink = [\"countdown\"]\nblink_interval = 0.5");
+        let mut outputs = GpioOutputs::open(MockGpio::new(), vec![blink]).unwrap();
+        let start = Instant::now();
+        assert_eq!(levels(&mut outputs, 17, Phase::Idle, start, &[0]), [false]);
+        let times = [100, 350, 599, 600, 1099, 1100, 1600];
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Countdown, start, &times),
+            [true, true, true, false, false, true, false]
+        );
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Review, start, &[1700]),
+            [false]
+        );
+    }
+
+    #[test]
+    fn flashes_pulse_for_their_time_whatever_the_phase() {
+        let flash = output("line = 17\nflash = 0.2");
+        let other = output("line = 18");
+        let mut outputs = GpioOutputs::open(MockGpio::new(), vec![flash, other]).unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Countdown, start, &[0]),
+            [false]
+        );
+        outputs.flash(start + ms(100)).unwrap();
+        assert_eq!(outputs.backend.level(17), Some(true));
+        assert_eq!(outputs.backend.level(18), Some(false));
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Countdown, start, &[150, 299, 300]),
+            [true, true, false]
+        );
+        assert_eq!(
+            levels(&mut outputs, 17, Phase::Review, start, &[400]),
+            [false]
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use image::RgbImage;
 use rocketbooth_libav::{Frame, ScalingContext};
 
+use crate::{video_input::VideoInput, CaptureSource};
+
 pub fn frame_to_image(frame: &Frame) -> Result<image::RgbImage, Box<dyn std::error::Error>> {
     let mut scaled_frame = None;
     if !frame.is_rgb24() {
-        let mut dest = Frame::alloc_rgb24(frame.width() as i32, frame.height() as i32)
-            .ok_or("Allocating temporary frame failed")?;
+        let mut dest = Frame::alloc_rgb24(frame.width() as i32, frame.height() as i32)?;
         let mut scaler = ScalingContext::new(
             frame.width() as i32,
             frame.height() as i32,
@@ -13,8 +14,8 @@ pub fn frame_to_image(frame: &Frame) -> Result<image::RgbImage, Box<dyn std::err
             frame.width() as i32,
             frame.height() as i32,
             dest.format(),
-        );
-        scaler.scale(frame, &mut dest);
+        )?;
+        scaler.scale(frame, &mut dest)?;
         scaled_frame = Some(dest);
     }
 
@@ -23,3 +24,17 @@ pub fn frame_to_image(frame: &Frame) -> Result<image::RgbImage, Box<dyn std::err
     RgbImage::from_vec(frame.width() as u32, frame.height() as u32, bytes)
         .ok_or_else(|| "Not enough bytes copied from frame".into())
 }
+
+/// Opens the capture source just long enough to take a single image from it.
+pub fn grab_image(capture_source: &CaptureSource) -> crate::Result<RgbImage> {
+    let mut input = VideoInput::open(&capture_source.source)?;
+    for _ in 0..capture_source.warmup_frames {
+        input
+            .next_frame()?
+            .ok_or("Capture source ended during warmup")?;
+    }
+    let frame = input
+        .next_frame()?
+        .ok_or("Capture source ended before producing a frame")?;
+    frame_to_image(&frame)
+}
@@ -0,0 +1,22 @@
+use crate::config::{Action, Bindings};
+
+/// An input from outside SDL, eg a button on a GPIO pin, sent to the booth's event loop as an
+/// SDL custom event. It has to be registered with `EventSubsystem::register_custom_event` before
+/// it's sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    /// An event named in `controls.bindings.events`.
+    Named(String),
+    /// An action to take as it is.
+    Action(Action),
+}
+
+impl InputEvent {
+    /// What the event makes the booth do, if anything.
+    pub fn action(&self, bindings: &Bindings) -> Option<Action> {
+        match self {
+            Self::Named(name) => bindings.events.get(name).copied(),
+            Self::Action(action) => Some(*action),
+        }
+    }
+}
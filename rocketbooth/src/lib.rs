@@ -1,13 +1,54 @@
+mod camera;
+mod capture;
+mod compositor;
 mod config;
+mod control;
+mod display;
+mod framing;
+mod gpio;
 mod image_libav;
 mod image_sdl2;
+mod input;
 mod libav_sdl2;
+mod print;
+mod serial;
 mod state;
+mod text;
+mod theme;
+mod touch;
+mod video_input;
+mod widget;
 
-pub use config::{Config, Controls, ImageLayout, ImageSettings, VideoSource};
+pub use camera::Camera;
+pub use capture::{run_capture_command, CaptureBackend, CommandCapture, LibavCapture};
+pub use compositor::{Compositor, PrintTemplate, TemplateSlot};
+pub use config::{
+    Action, Bias, Bindings, CaptureCommand, CaptureSource, Config, ControlSettings, Controls,
+    Copies, Edge, EventInfo, GpioInput, GpioOutput, Grid, ImageLayout, ImageSettings, Orientation,
+    Phase, PrinterSettings, Prompts, RegionBinding, Review, ReviewAction, SerialSettings, Slot,
+    Timing, TouchSettings, VideoSource,
+};
+pub use control::{
+    bind_control_socket, send_command, serve_control, ControlCommand, ControlRequest, Reply, Status,
+};
+pub use display::Screen;
+pub use framing::{CropRegion, FitMode, Framing};
+#[cfg(feature = "gpio")]
+pub use gpio::ChipGpio;
+pub use gpio::{GpioBackend, GpioButtons, GpioOutputs};
 pub use image_sdl2::image_to_texture;
+pub use input::InputEvent;
 pub use libav_sdl2::{frame_to_texture, FrameTextureUpdater};
-pub use state::{Context, ContextBuilder, State};
+pub use print::{
+    print_and_follow, print_to, JobState, PrintJob, PrintQueue, PrintStatus, PrinterProblem,
+};
+pub use serial::{connect_serial, open_serial, read_commands, SerialPort, StateReporter};
+pub use state::{run_command, Context, ContextBuilder, State};
+pub use text::{Align, TextField};
+pub use theme::{Anchor, Scale, Theme, Themed};
+pub use touch::{Gesture, SwipeDirection, TouchTracker, TOUCH_MOUSE_ID};
+pub use video_input::ConnectionStatus;
+pub use widget::{Area, Button, ButtonId, ButtonStyle, OperatorChoice};
 
 type Error = Box<dyn std::error::Error>;
 type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
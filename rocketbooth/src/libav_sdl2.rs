@@ -1,18 +1,9 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc, Mutex,
-};
-
-use rocketbooth_libav::{
-    Dictionary, Format, FormatContext, Frame, Packet, ReceiveResult, ScalingContext,
-};
+use rocketbooth_libav::{Frame, ScalingContext};
 use sdl2::{
     pixels::PixelFormatEnum,
     render::{Texture, TextureCreator},
 };
 
-use crate::VideoSource;
-
 pub fn frame_to_texture<'t, T>(
     frame: &Frame,
     display_size: Option<(usize, usize)>,
@@ -36,8 +27,7 @@ pub fn frame_to_texture<'t, T>(
         updater.update_via = UpdateVia::Yuv;
         Some(PixelFormatEnum::IYUV)
     } else if frame.is_any_rgb_format() {
-        let dest = Frame::alloc_rgb24(dest_width, dest_height)
-            .ok_or("Allocating temporary frame failed")?;
+        let dest = Frame::alloc_rgb24(dest_width, dest_height)?;
         let scaler = ScalingContext::new(
             frame.width() as i32,
             frame.height() as i32,
@@ -45,12 +35,11 @@ pub fn frame_to_texture<'t, T>(
             dest_width,
             dest_height,
             dest.format(),
-        );
+        )?;
         updater.scaler = Some((scaler, dest));
         Some(PixelFormatEnum::RGB24)
     } else {
-        let dest = Frame::alloc_yuv420p(dest_width, dest_height)
-            .ok_or("Allocating temporary frame failed")?;
+        let dest = Frame::alloc_yuv420p(dest_width, dest_height)?;
         let scaler = ScalingContext::new(
             frame.width() as i32,
             frame.height() as i32,
@@ -58,7 +47,7 @@ pub fn frame_to_texture<'t, T>(
             dest_width,
             dest_height,
             dest.format(),
-        );
+        )?;
         updater.scaler = Some((scaler, dest));
         updater.update_via = UpdateVia::Yuv;
         Some(PixelFormatEnum::IYUV)
@@ -92,10 +81,13 @@ impl FrameTextureUpdater {
         frame: &Frame,
         texture: &mut Texture,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let scaled_frame = self.scaler.as_mut().map(|(scaler, dest)| -> &Frame {
-            scaler.scale(frame, dest);
-            dest
-        });
+        let scaled_frame = match self.scaler.as_mut() {
+            Some((scaler, dest)) => {
+                scaler.scale(frame, dest)?;
+                Some(&*dest)
+            }
+            None => None,
+        };
 
         let frame = scaled_frame.unwrap_or(frame);
         match self.update_via {
@@ -117,10 +109,9 @@ impl FrameTextureUpdater {
     }
 }
 
+/// Keeps a texture up to date with the most recent preview frame.
 pub struct FrameTextureManager<'t, T> {
     frame: Option<Frame>,
-    shared_frame: Arc<Mutex<Option<Frame>>>,
-    control_bit: Arc<AtomicBool>,
     texture_creator: &'t TextureCreator<T>,
     updater_and_texture: Option<(FrameTextureUpdater, Texture<'t>)>,
     display_size: Option<(usize, usize)>,
@@ -128,68 +119,46 @@ pub struct FrameTextureManager<'t, T> {
 
 impl<'t, T> FrameTextureManager<'t, T> {
     pub fn new(
-        video_source: &VideoSource,
+        display_size: Option<(usize, usize)>,
         texture_creator: &'t TextureCreator<T>,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let path = video_source.path.clone();
-        let format = video_source.format.as_deref().and_then(Format::from_name);
-        let video_codec = video_source.video_codec.clone();
-        let display_size = video_source.display_size;
-        let options = if video_source.options.is_empty() {
-            None
-        } else {
-            Some(Dictionary::from(&video_source.options))
-        };
-        let updater_and_texture = None;
-        let shared_frame = Arc::new(Mutex::new(None));
-        let control_bit = Arc::new(AtomicBool::new(true));
-        std::thread::spawn({
-            let shared_frame = Arc::clone(&shared_frame);
-            let control_bit = Arc::clone(&control_bit);
-            move || {
-                if let Err(e) = Self::read_video_frames(
-                    path.as_str(),
-                    format,
-                    video_codec,
-                    options,
-                    shared_frame,
-                    control_bit,
-                ) {
-                    println!("{e:?}");
-                }
-            }
-        });
-        Ok(Self {
-            control_bit,
+    ) -> Self {
+        Self {
             frame: None,
-            shared_frame,
             texture_creator,
-            updater_and_texture,
+            updater_and_texture: None,
             display_size,
-        })
+        }
+    }
+
+    pub fn update(&mut self, frame: Frame) {
+        // A reconnected source may come back with a different size or pixel format.
+        let is_compatible = self.frame.as_ref().is_some_and(|previous| {
+            (previous.width(), previous.height(), previous.format())
+                == (frame.width(), frame.height(), frame.format())
+        });
+        match self.updater_and_texture.as_mut() {
+            Some((updater, texture)) if is_compatible => {
+                let _ = updater.update(&frame, texture);
+            }
+            _ => {
+                self.updater_and_texture =
+                    frame_to_texture(&frame, self.display_size, self.texture_creator).ok()
+            }
+        }
+        self.frame = Some(frame);
+    }
+
+    /// Forgets the current frame, eg when the source is closed.
+    pub fn clear(&mut self) {
+        self.frame = None;
+        self.updater_and_texture = None;
     }
 
     pub fn frame_ref(&self) -> Option<&Frame> {
         self.frame.as_ref()
     }
 
-    pub fn texture_ref(&mut self) -> Option<&Texture<'t>> {
-        if let Some(frame) = self
-            .shared_frame
-            .lock()
-            .as_mut()
-            .ok()
-            .and_then(|frame| frame.take())
-        {
-            match self.updater_and_texture.as_mut() {
-                Some((updater, texture)) => updater.update(&frame, texture).ok()?,
-                None => {
-                    self.updater_and_texture =
-                        frame_to_texture(&frame, self.display_size, self.texture_creator).ok()
-                }
-            }
-            self.frame = Some(frame);
-        }
+    pub fn texture_ref(&self) -> Option<&Texture<'t>> {
         self.updater_and_texture
             .as_ref()
             .map(|(_, texture)| texture)
@@ -200,48 +169,4 @@ impl<'t, T> FrameTextureManager<'t, T> {
             .as_mut()
             .map(|(_, texture)| texture)
     }
-
-    fn read_video_frames(
-        src: &str,
-        format: Option<Format>,
-        video_codec: Option<String>,
-        options: Option<Dictionary>,
-        shared_frame: Arc<Mutex<Option<Frame>>>,
-        control_bit: Arc<AtomicBool>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut context = FormatContext::open(src, format, options).ok_or("Failed to open file")?;
-        context.find_stream_info();
-        let video_stream = context
-            .streams()
-            .find(|stream| stream.is_video())
-            .ok_or("No video stream")?;
-        let mut decoder = video_stream
-            .create_decoder(video_codec.as_deref())
-            .ok_or("Codec failed to initialize")?;
-        let mut packet = Packet::new().ok_or("Could not allocate packet")?;
-        'read: while control_bit.load(Ordering::Relaxed) && context.read_into(&mut packet) {
-            if packet.stream_index() == video_stream.index() {
-                decoder.send(&mut packet);
-                let mut frame = Frame::new().ok_or("Failed to initialize frame")?;
-                'receive: loop {
-                    let result = decoder.receive(&mut frame);
-                    match result {
-                        ReceiveResult::Done => break 'read,
-                        ReceiveResult::Pending | ReceiveResult::Error => break 'receive,
-                        ReceiveResult::Success => {
-                            *shared_frame.lock().unwrap() = Some(frame);
-                            frame = Frame::new().ok_or("Failed to reinitialize frame")?;
-                        }
-                    }
-                }
-            }
-        }
-        Ok(())
-    }
-}
-
-impl<'t, T> Drop for FrameTextureManager<'t, T> {
-    fn drop(&mut self) {
-        self.control_bit.store(false, Ordering::Relaxed)
-    }
 }
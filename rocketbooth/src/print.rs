@@ -0,0 +1,525 @@
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::config::PrinterSettings;
+
+/// How far a print job has got.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrintStatus {
+    /// Being handed to CUPS.
+    Sending,
+    /// Waiting in the queue, or held there by a problem at the printer.
+    Queued,
+    Printing,
+    Done,
+    /// The job couldn't be sent, or was cancelled or aborted.
+    Failed(String),
+}
+
+/// Something wrong at the printer that someone needs to see to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrinterProblem {
+    OutOfPaper,
+    OutOfInk,
+    PaperJam,
+    Offline,
+    /// Any other error the printer reports, by its IPP keyword or message.
+    Other(String),
+}
+
+impl PrinterProblem {
+    /// The problem described by a `printer-state-reasons` keyword, if it's one that stops
+    /// printing. Warnings and reports, eg `media-low-warning`, aren't problems.
+    pub fn from_reason(reason: &str) -> Option<Self> {
+        if reason.ends_with("-warning") || reason.ends_with("-report") {
+            return None;
+        }
+        let keyword = reason.strip_suffix("-error").unwrap_or(reason);
+        Some(match keyword {
+            "media-empty" | "media-needed" | "input-tray-missing" => Self::OutOfPaper,
+            "marker-supply-empty" | "toner-empty" | "marker-waste-full" | "ink-empty" => {
+                Self::OutOfInk
+            }
+            "media-jam" => Self::PaperJam,
+            "offline" | "shutdown" | "timed-out" | "connecting-to-device" => Self::Offline,
+            "paused" | "moving-to-paused" => return None,
+            _ => Self::Other(keyword.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for PrinterProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfPaper => f.write_str("The printer is out of paper"),
+            Self::OutOfInk => f.write_str("The printer is out of ink"),
+            Self::PaperJam => f.write_str("The paper is jammed"),
+            Self::Offline => f.write_str("The printer is offline"),
+            Self::Other(reason) => write!(f, "The printer reported a problem: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Progress {
+    status: PrintStatus,
+    /// Whether the printer's queue has taken the job.
+    accepted: bool,
+    problems: Vec<PrinterProblem>,
+    dismissed: bool,
+}
+
+/// A print job followed in the background, shared between the thread following it and the
+/// booth's screens.
+#[derive(Clone, Debug)]
+pub struct PrintJob {
+    progress: Arc<Mutex<Progress>>,
+}
+
+impl Default for PrintJob {
+    fn default() -> Self {
+        Self {
+            progress: Arc::new(Mutex::new(Progress {
+                status: PrintStatus::Sending,
+                accepted: false,
+                problems: vec![],
+                dismissed: false,
+            })),
+        }
+    }
+}
+
+impl PrintJob {
+    pub fn status(&self) -> PrintStatus {
+        self.progress.lock().unwrap().status.clone()
+    }
+
+    /// Whether the printer's queue has taken the job, even if it's failed since.
+    pub fn accepted(&self) -> bool {
+        self.progress.lock().unwrap().accepted
+    }
+
+    /// The problems holding up the job, unless they've been dismissed.
+    pub fn problems(&self) -> Vec<PrinterProblem> {
+        let progress = self.progress.lock().unwrap();
+        if progress.dismissed {
+            vec![]
+        } else {
+            progress.problems.clone()
+        }
+    }
+
+    /// Hides the current problems until the printer reports different ones.
+    pub fn dismiss(&self) {
+        self.progress.lock().unwrap().dismissed = true;
+    }
+
+    pub(crate) fn accept(&self) {
+        self.progress.lock().unwrap().accepted = true;
+    }
+
+    fn update(&self, status: PrintStatus, problems: Vec<PrinterProblem>) {
+        let mut progress = self.progress.lock().unwrap();
+        if problems != progress.problems {
+            progress.dismissed = false;
+        }
+        progress.status = status;
+        progress.problems = problems;
+    }
+}
+
+/// Where a job is in a printer's queue, as `job-state` reports it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Held,
+    Processing,
+    Stopped,
+    Canceled,
+    Aborted,
+    Completed,
+}
+
+impl JobState {
+    /// Whether the job has left the queue, one way or another.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Canceled | Self::Aborted | Self::Completed)
+    }
+}
+
+/// A printer's queue, as far as sending it a job and following the job goes. CUPS is the one
+/// the booth prints with, with the `cups` feature.
+pub trait PrintQueue {
+    /// Sends the file at `path` with the IPP job `options`, returning the new job's ID.
+    fn submit(
+        &mut self,
+        path: &Path,
+        title: &str,
+        options: &[(String, String)],
+    ) -> crate::Result<i32>;
+
+    /// The state of a job, or `None` if the queue no longer knows about it.
+    fn job_state(&mut self, job_id: i32) -> crate::Result<Option<JobState>>;
+
+    /// The printer's `printer-state-reasons` keywords.
+    fn printer_reasons(&mut self) -> crate::Result<Vec<String>>;
+
+    fn cancel_job(&mut self, job_id: i32) -> crate::Result<()>;
+}
+
+/// The CUPS queue for a printer.
+#[cfg(feature = "cups")]
+pub struct CupsQueue {
+    name: String,
+}
+
+#[cfg(feature = "cups")]
+impl CupsQueue {
+    /// The queue for the printer with the given name, or the default printer.
+    pub fn open(name: Option<&str>) -> crate::Result<Self> {
+        let name = rocketbooth_cups::printer(name)?.name;
+        Ok(Self { name })
+    }
+}
+
+#[cfg(feature = "cups")]
+impl PrintQueue for CupsQueue {
+    fn submit(
+        &mut self,
+        path: &Path,
+        title: &str,
+        options: &[(String, String)],
+    ) -> crate::Result<i32> {
+        let options = options
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        Ok(rocketbooth_cups::print_file(
+            &self.name, path, title, options,
+        )?)
+    }
+
+    fn job_state(&mut self, job_id: i32) -> crate::Result<Option<JobState>> {
+        use rocketbooth_cups::JobState as Cups;
+        let state = rocketbooth_cups::job_state(&self.name, job_id)?;
+        Ok(state.map(|state| match state {
+            Cups::Pending => JobState::Pending,
+            Cups::Held => JobState::Held,
+            Cups::Processing => JobState::Processing,
+            Cups::Stopped => JobState::Stopped,
+            Cups::Canceled => JobState::Canceled,
+            Cups::Aborted => JobState::Aborted,
+            Cups::Completed => JobState::Completed,
+        }))
+    }
+
+    fn printer_reasons(&mut self) -> crate::Result<Vec<String>> {
+        Ok(rocketbooth_cups::printer(Some(&self.name))?.reasons)
+    }
+
+    fn cancel_job(&mut self, job_id: i32) -> crate::Result<()> {
+        Ok(rocketbooth_cups::cancel_job(&self.name, job_id)?)
+    }
+}
+
+/// Prints `copies` of the image at `path`, then follows the job until it leaves the queue,
+/// reporting its progress through `job`. Blocks for as long as that takes, so it's run on its
+/// own thread.
+pub fn print_and_follow(settings: &PrinterSettings, path: &Path, copies: u32, job: &PrintJob) {
+    if let Err(e) = print_with_cups(settings, path, copies, job) {
+        eprintln!("Printing {} failed: {e}", path.display());
+        job.update(PrintStatus::Failed(e.to_string()), vec![]);
+    }
+}
+
+#[cfg(feature = "cups")]
+fn print_with_cups(
+    settings: &PrinterSettings,
+    path: &Path,
+    copies: u32,
+    job: &PrintJob,
+) -> crate::Result<()> {
+    let mut queue = CupsQueue::open(settings.name.as_deref())?;
+    print_to(&mut queue, settings, path, copies, job)
+}
+
+#[cfg(not(feature = "cups"))]
+fn print_with_cups(_: &PrinterSettings, _: &Path, _: u32, _: &PrintJob) -> crate::Result<()> {
+    Err("Rocketbooth was built without the cups feature".into())
+}
+
+/// Prints `copies` of the image at `path` on `queue`, then follows the job until it leaves the
+/// queue or times out, when it's cancelled.
+pub fn print_to(
+    queue: &mut impl PrintQueue,
+    settings: &PrinterSettings,
+    path: &Path,
+    copies: u32,
+    job: &PrintJob,
+) -> crate::Result<()> {
+    use std::time::{Duration, Instant};
+
+    let title = path
+        .file_name()
+        .map_or("rocketbooth".into(), |name| name.to_string_lossy());
+    let mut options = settings.job_options();
+    options.retain(|(name, _)| name != "copies");
+    options.push(("copies".into(), copies.to_string()));
+    let job_id = queue.submit(path, &title, &options)?;
+    job.accept();
+    let poll_interval = Duration::try_from_secs_f64(settings.poll_interval).unwrap_or_default();
+    let deadline = (settings.timeout > 0.0).then(|| {
+        Instant::now() + Duration::try_from_secs_f64(settings.timeout).unwrap_or_default()
+    });
+    loop {
+        let state = queue.job_state(job_id)?;
+        // The printer is asked too, since a job waiting on an empty paper tray looks like any
+        // other pending job.
+        let problems = match queue.printer_reasons() {
+            Ok(reasons) => reasons
+                .iter()
+                .filter_map(|reason| PrinterProblem::from_reason(reason))
+                .collect(),
+            Err(e) => vec![PrinterProblem::Other(e.to_string())],
+        };
+        let status = match state {
+            None | Some(JobState::Completed) => PrintStatus::Done,
+            Some(JobState::Canceled) => PrintStatus::Failed("The print job was cancelled".into()),
+            Some(JobState::Aborted) => PrintStatus::Failed("The print job was aborted".into()),
+            Some(JobState::Processing) => PrintStatus::Printing,
+            Some(JobState::Pending | JobState::Held | JobState::Stopped) => PrintStatus::Queued,
+        };
+        let finished = state.is_none_or(JobState::is_finished);
+        job.update(status, if finished { vec![] } else { problems });
+        if finished {
+            return Ok(());
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            queue.cancel_job(job_id)?;
+            return Err("The print job timed out and was cancelled".into());
+        }
+        std::thread::sleep(poll_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    #[test]
+    fn reasons_that_stop_printing_are_problems() {
+        let cases = [
+            ("media-empty-error", PrinterProblem::OutOfPaper),
+            ("media-needed", PrinterProblem::OutOfPaper),
+            ("input-tray-missing-error", PrinterProblem::OutOfPaper),
+            ("marker-supply-empty-error", PrinterProblem::OutOfInk),
+            ("toner-empty", PrinterProblem::OutOfInk),
+            ("media-jam-error", PrinterProblem::PaperJam),
+            ("offline", PrinterProblem::Offline),
+            ("connecting-to-device", PrinterProblem::Offline),
+            (
+                "cover-open-error",
+                PrinterProblem::Other("cover-open".into()),
+            ),
+        ];
+        for (reason, problem) in cases {
+            assert_eq!(
+                PrinterProblem::from_reason(reason),
+                Some(problem),
+                "{reason}"
+            );
+        }
+    }
+
+    #[test]
+    fn warnings_reports_and_pauses_are_not_problems() {
+        for reason in [
+            "media-low-warning",
+            "toner-low-report",
+            "paused",
+            "moving-to-paused",
+        ] {
+            assert_eq!(PrinterProblem::from_reason(reason), None, "{reason}");
+        }
+    }
+
+    #[test]
+    fn problems_come_back_after_dismissal_when_they_change() {
+        let job = PrintJob::default();
+        job.update(PrintStatus::Queued, vec![PrinterProblem::OutOfPaper]);
+        job.dismiss();
+        assert!(job.problems().is_empty());
+        job.update(PrintStatus::Queued, vec![PrinterProblem::OutOfPaper]);
+        assert!(job.problems().is_empty());
+        job.update(PrintStatus::Queued, vec![PrinterProblem::PaperJam]);
+        assert_eq!(job.problems(), vec![PrinterProblem::PaperJam]);
+    }
+
+    /// A queue that steps through the job states and printer reasons it's given, one per poll,
+    /// staying on the last. It notes what the job showed at each poll.
+    struct MockQueue {
+        steps: VecDeque<(Option<JobState>, Vec<&'static str>)>,
+        reasons: Vec<&'static str>,
+        job: PrintJob,
+        seen: Vec<(PrintStatus, Vec<PrinterProblem>)>,
+        submitted: Vec<(String, String)>,
+        cancelled: Vec<i32>,
+    }
+
+    impl MockQueue {
+        fn new(steps: impl IntoIterator<Item = (Option<JobState>, Vec<&'static str>)>) -> Self {
+            Self {
+                steps: steps.into_iter().collect(),
+                reasons: vec![],
+                job: PrintJob::default(),
+                seen: vec![],
+                submitted: vec![],
+                cancelled: vec![],
+            }
+        }
+
+        /// Prints through the queue, returning the result and what the job showed at each
+        /// poll, and once it was over.
+        fn print(&mut self, settings: &str) -> crate::Result<()> {
+            let settings: PrinterSettings = toml::from_str(settings).unwrap();
+            let job = self.job.clone();
+            let result = print_to(self, &settings, Path::new("/photos/1.jpg"), 2, &job);
+            self.seen.push((job.status(), job.problems()));
+            result
+        }
+
+        fn statuses(&self) -> Vec<PrintStatus> {
+            self.seen.iter().map(|(status, _)| status.clone()).collect()
+        }
+    }
+
+    impl PrintQueue for MockQueue {
+        fn submit(
+            &mut self,
+            _: &Path,
+            _: &str,
+            options: &[(String, String)],
+        ) -> crate::Result<i32> {
+            self.submitted = options.to_vec();
+            Ok(7)
+        }
+
+        fn job_state(&mut self, job_id: i32) -> crate::Result<Option<JobState>> {
+            assert_eq!(job_id, 7);
+            self.seen.push((self.job.status(), self.job.problems()));
+            let (state, reasons) = match self.steps.len() {
+                1 => self.steps[0].clone(),
+                _ => self.steps.pop_front().unwrap(),
+            };
+            self.reasons = reasons;
+            Ok(state)
+        }
+
+        fn printer_reasons(&mut self) -> crate::Result<Vec<String>> {
+            Ok(self
+                .reasons
+                .iter()
+                .map(|reason| reason.to_string())
+                .collect())
+        }
+
+        fn cancel_job(&mut self, job_id: i32) -> crate::Result<()> {
+            self.cancelled.push(job_id);
+            Ok(())
+        }
+    }
+
+    const QUICK: &str = "poll_interval = 0.0";
+
+    #[test]
+    fn jobs_are_followed_through_the_queue_until_done() {
+        let mut queue = MockQueue::new([
+            (Some(JobState::Pending), vec![]),
+            (Some(JobState::Processing), vec![]),
+            (Some(JobState::Completed), vec![]),
+        ]);
+        queue.print(QUICK).unwrap();
+        assert_eq!(
+            queue.statuses(),
+            [
+                PrintStatus::Sending,
+                PrintStatus::Queued,
+                PrintStatus::Printing,
+                PrintStatus::Done,
+            ]
+        );
+        assert!(queue.submitted.contains(&("copies".into(), "2".into())));
+        assert!(queue.cancelled.is_empty());
+    }
+
+    #[test]
+    fn jobs_the_queue_has_forgotten_are_done() {
+        let mut queue = MockQueue::new([(Some(JobState::Processing), vec![]), (None, vec![])]);
+        queue.print(QUICK).unwrap();
+        assert_eq!(queue.statuses().last(), Some(&PrintStatus::Done));
+    }
+
+    #[test]
+    fn printer_problems_show_until_they_clear() {
+        let mut queue = MockQueue::new([
+            (
+                Some(JobState::Pending),
+                vec!["media-empty-error", "toner-low-warning"],
+            ),
+            (Some(JobState::Pending), vec!["media-empty-error"]),
+            (Some(JobState::Processing), vec![]),
+            (Some(JobState::Completed), vec!["media-jam-error"]),
+        ]);
+        queue.print(QUICK).unwrap();
+        let problems: Vec<_> = queue
+            .seen
+            .iter()
+            .map(|(_, problems)| problems.clone())
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                vec![],
+                vec![PrinterProblem::OutOfPaper],
+                vec![PrinterProblem::OutOfPaper],
+                vec![],
+                // A finished job has nothing left to hold it up.
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn cancelled_and_aborted_jobs_fail() {
+        for (state, error) in [
+            (JobState::Canceled, "The print job was cancelled"),
+            (JobState::Aborted, "The print job was aborted"),
+        ] {
+            let mut queue =
+                MockQueue::new([(Some(JobState::Processing), vec![]), (Some(state), vec![])]);
+            queue.print(QUICK).unwrap();
+            assert_eq!(
+                queue.statuses().last(),
+                Some(&PrintStatus::Failed(error.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn jobs_that_time_out_are_cancelled() {
+        let mut queue = MockQueue::new([(Some(JobState::Held), vec![])]);
+        let error = queue
+            .print("poll_interval = 0.01\ntimeout = 0.05")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The print job timed out and was cancelled"
+        );
+        assert_eq!(queue.cancelled, [7]);
+        assert_eq!(queue.statuses().last(), Some(&PrintStatus::Queued));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    config::{Phase, SerialSettings},
+    input::InputEvent,
+};
+
+/// How long to wait before opening the controller's port again, after it's failed or been
+/// unplugged.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Opens a serial port as a raw line at `baud_rate`, eg for an Arduino on "/dev/ttyACM0". Any
+/// terminal device will do, which is how a pseudo-terminal stands in for one in testing.
+pub fn open_serial(path: &str, baud_rate: u32) -> crate::Result<File> {
+    let port = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)
+        .map_err(|e| format!("Failed to open {path}: {e}"))?;
+    let speed = match baud_rate {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        _ => return Err(format!("Unsupported baud rate {baud_rate}").into()),
+    };
+    let fd = port.as_raw_fd();
+    let configure = || -> io::Result<()> {
+        // Safety: the termios is only used after tcgetattr has filled it in, and `fd` is open
+        // for as long as `port` is.
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            check(libc::tcgetattr(fd, &mut termios))?;
+            libc::cfmakeraw(&mut termios);
+            // Ignore the modem control lines, so a port without them still reads.
+            termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+            // Block until at least a byte arrives.
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            check(libc::cfsetispeed(&mut termios, speed))?;
+            check(libc::cfsetospeed(&mut termios, speed))?;
+            check(libc::tcsetattr(fd, libc::TCSANOW, &termios))
+        }
+    };
+    configure().map_err(|e| format!("Failed to set up {path}: {e}"))?;
+    Ok(port)
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reads the controller's commands a line at a time until it's closed, sending each as an
+/// event named by its first word, eg `COIN` for `COIN 1`. The rest of the line is for the
+/// controller's own use, and blank lines are skipped.
+pub fn read_commands(
+    reader: impl BufRead,
+    mut send: impl FnMut(InputEvent) -> crate::Result<()>,
+) -> crate::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(command) = line.split_whitespace().next() {
+            send(InputEvent::Named(command.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// The booth's end of a serial controller kept connected by `connect_serial`, for telling it
+/// what the booth is doing. It's told the booth's state afresh each time it's reconnected.
+#[derive(Clone, Default)]
+pub struct SerialPort(Arc<Mutex<Option<StateReporter<File>>>>);
+
+impl SerialPort {
+    fn connect(&self, port: Option<File>) {
+        *self.0.lock().unwrap() = port.map(StateReporter::new);
+    }
+
+    /// Reports the booth's state as `StateReporter::report` does, failing with `NotConnected`
+    /// while the controller's unplugged. A report that fails disconnects it until it's opened
+    /// again.
+    pub fn report(&self, phase: Phase, shot: Option<usize>) -> io::Result<()> {
+        let mut reporter = self.0.lock().unwrap();
+        let result = (reporter.as_mut())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?
+            .report(phase, shot);
+        if result.is_err() {
+            *reporter = None;
+        }
+        result
+    }
+}
+
+/// Keeps the controller in `settings` connected to `port`, reading its commands with
+/// `read_commands` and opening it again whenever it fails, so a controller that's unplugged or
+/// not there yet doesn't stop the booth. Only returns once `send` fails.
+pub fn connect_serial(
+    settings: &SerialSettings,
+    port: &SerialPort,
+    mut send: impl FnMut(InputEvent) -> crate::Result<()>,
+) -> crate::Result<()> {
+    let mut connected = true;
+    loop {
+        match open_serial(&settings.path, settings.baud_rate) {
+            Ok(file) => {
+                if !connected {
+                    eprintln!("Serial controller connected on {}", settings.path);
+                }
+                connected = true;
+                port.connect(Some(file.try_clone()?));
+                // The booth going away stops the reading, but isn't the port's failure.
+                let mut stopped = None;
+                let read = read_commands(BufReader::new(file), |event| {
+                    send(event).map_err(|e| {
+                        let message = e.to_string();
+                        stopped = Some(e);
+                        message.into()
+                    })
+                });
+                port.connect(None);
+                if let Some(e) = stopped {
+                    return Err(e);
+                }
+                match read {
+                    Ok(()) => eprintln!("Serial controller disconnected from {}", settings.path),
+                    Err(e) => eprintln!("Serial controller disconnected: {e}"),
+                }
+            }
+            // Only the first failure is reported, rather than every retry.
+            Err(e) if connected => {
+                eprintln!("Serial controller unavailable, retrying: {e}");
+                connected = false;
+            }
+            Err(_) => {}
+        }
+        std::thread::sleep(RECONNECT_INTERVAL);
+    }
+}
+
+/// Tells the controller what the booth is doing, with a line such as `STATE capture 3` each
+/// time it changes, so it can set its lights to match.
+pub struct StateReporter<W> {
+    writer: W,
+    last: Option<String>,
+}
+
+impl<W: Write> StateReporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, last: None }
+    }
+
+    /// Sends the booth's `phase`, and which photo of the session is being taken while it's
+    /// counting down, if they've changed since they were last sent.
+    pub fn report(&mut self, phase: Phase, shot: Option<usize>) -> io::Result<()> {
+        let mut message = format!("STATE {}", phase.state_name());
+        if let Some(shot) = shot {
+            message += &format!(" {shot}");
+        }
+        if self.last.as_ref() == Some(&message) {
+            return Ok(());
+        }
+        writeln!(self.writer, "{message}")?;
+        self.writer.flush()?;
+        self.last = Some(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CStr,
+        io::{BufRead, BufReader, Write},
+        os::unix::io::FromRawFd,
+        sync::mpsc::channel,
+    };
+
+    use super::*;
+
+    /// Opens a new pseudo-terminal, returning the controller's end and the path of the
+    /// booth's, which stands in for the serial port.
+    fn open_pty() -> (File, String) {
+        // Safety: the descriptor is owned by the returned file, which closes it, and the name
+        // is copied out of a buffer that's nul-terminated on success.
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0, "{}", io::Error::last_os_error());
+            let controller = File::from_raw_fd(fd);
+            let mut name = [0 as libc::c_char; 128];
+            assert!(
+                libc::grantpt(fd) == 0
+                    && libc::unlockpt(fd) == 0
+                    && libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) == 0,
+                "{}",
+                io::Error::last_os_error()
+            );
+            let path = CStr::from_ptr(name.as_ptr()).to_str().unwrap().to_string();
+            (controller, path)
+        }
+    }
+
+    fn read_line(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    fn named(name: &str) -> InputEvent {
+        InputEvent::Named(name.into())
+    }
+
+    #[test]
+    fn commands_are_sent_by_their_first_word() {
+        let (mut controller, path) = open_pty();
+        let port = open_serial(&path, 115200).unwrap();
+        controller
+            .write_all(b"PRESS\nCOIN 1\n\n  \nHELLO there\n")
+            .unwrap();
+        let mut events = vec![];
+        // The port doesn't end while the controller's open, so reading's stopped by failing.
+        let result = read_commands(BufReader::new(port), |event| {
+            events.push(event);
+            match events.len() {
+                3 => Err("Enough".into()),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(result.unwrap_err().to_string(), "Enough");
+        assert_eq!(events, [named("PRESS"), named("COIN"), named("HELLO")]);
+    }
+
+    #[test]
+    fn unsupported_baud_rates_are_rejected() {
+        let (_controller, path) = open_pty();
+        assert!(open_serial(&path, 1000).is_err());
+        assert!(open_serial("/dev/rocketbooth-missing", 9600).is_err());
+    }
+
+    #[test]
+    fn states_are_sent_as_they_change() {
+        let (controller, path) = open_pty();
+        let mut reporter = StateReporter::new(open_serial(&path, 9600).unwrap());
+        let session = [
+            (Phase::Idle, None),
+            (Phase::Welcome, None),
+            (Phase::Countdown, Some(1)),
+            (Phase::Countdown, Some(1)),
+            (Phase::Countdown, Some(2)),
+            (Phase::Debrief, None),
+        ];
+        for (phase, shot) in session {
+            reporter.report(phase, shot).unwrap();
+        }
+        let mut controller = BufReader::new(controller);
+        let lines: Vec<_> = (0..5).map(|_| read_line(&mut controller)).collect();
+        assert_eq!(
+            lines,
+            [
+                "STATE idle\n",
+                "STATE welcome\n",
+                "STATE capture 1\n",
+                "STATE capture 2\n",
+                "STATE debrief\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_state_is_sent_again_once_reconnected() {
+        let (controller, path) = open_pty();
+        let port = SerialPort::default();
+        let error = port.report(Phase::Idle, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        port.connect(Some(open_serial(&path, 9600).unwrap()));
+        port.report(Phase::Idle, None).unwrap();
+        port.report(Phase::Idle, None).unwrap();
+        port.connect(None);
+        assert!(port.report(Phase::Idle, None).is_err());
+        port.connect(Some(open_serial(&path, 9600).unwrap()));
+        port.report(Phase::Idle, None).unwrap();
+        port.report(Phase::Welcome, None).unwrap();
+        let mut controller = BufReader::new(controller);
+        let lines: Vec<_> = (0..3).map(|_| read_line(&mut controller)).collect();
+        assert_eq!(lines, ["STATE idle\n", "STATE idle\n", "STATE welcome\n"]);
+    }
+
+    #[test]
+    fn connected_controllers_are_read_and_written_until_the_booth_stops() {
+        let (controller, path) = open_pty();
+        let settings = SerialSettings {
+            path,
+            baud_rate: 9600,
+        };
+        let port = SerialPort::default();
+        let (sender, events) = channel();
+        let reader = {
+            let port = port.clone();
+            std::thread::spawn(move || {
+                connect_serial(&settings, &port, |event| match event {
+                    InputEvent::Named(name) if name == "QUIT" => Err("Booth closed".into()),
+                    event => Ok(sender.send(event)?),
+                })
+                .map_err(|e| e.to_string())
+            })
+        };
+        // Nothing's written to the controller until the port's set up, or it would be echoed.
+        while port.report(Phase::Idle, None).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let mut to_booth = controller.try_clone().unwrap();
+        assert_eq!(read_line(&mut BufReader::new(controller)), "STATE idle\n");
+        to_booth.write_all(b"PRESS\nQUIT\n").unwrap();
+        assert_eq!(events.recv().unwrap(), named("PRESS"));
+        assert_eq!(reader.join().unwrap().unwrap_err(), "Booth closed");
+        let error = port.report(Phase::Idle, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+    }
+}
@@ -1,18 +1,37 @@
 use std::{
+    collections::HashMap,
     error::Error,
     fs::File,
     io::{BufReader, Read},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::{config::Config, image_sdl2::image_to_texture};
+use crate::{
+    camera::Camera,
+    compositor::Compositor,
+    config::{Config, ImageLayout, Phase},
+    display::Screen,
+    gpio::GpioOutputs,
+    image_sdl2::image_to_texture,
+    print::{PrintJob, PrintStatus},
+    serial::SerialPort,
+    text::load_font,
+    theme::Theme,
+    touch::TouchTracker,
+    widget::ButtonId,
+};
+use ab_glyph::FontArc;
 use image::RgbaImage;
 use sdl2::render::{Texture, TextureCreator};
+use time::OffsetDateTime;
 
 pub struct ContextBuilder {
     config: Config,
     path: PathBuf,
-    prompts: [RgbaImage; 7],
+    theme: Theme<RgbaImage>,
+    font: Option<FontArc>,
+    compositor: Compositor,
 }
 
 impl ContextBuilder {
@@ -21,60 +40,278 @@ impl ContextBuilder {
         let mut f = BufReader::new(f);
         let mut buf = String::new();
         f.read_to_string(&mut buf)?;
-        let config = toml::from_str(buf.as_str())?;
-        let mut prompt_root = PathBuf::from(path.as_ref());
-        prompt_root.pop();
-        let load_image = |s: &str| -> Result<RgbaImage, Box<dyn Error>> {
+        let config: Config = toml::from_str(buf.as_str())?;
+        config.validate()?;
+        let mut config_dir = PathBuf::from(path.as_ref());
+        config_dir.pop();
+        let theme = match &config.theme {
+            Some(theme_dir) => Theme::from_dir(&config_dir.join(theme_dir))?,
+            None => Theme::from_prompts(&config_dir, &config)?,
+        };
+        let theme = theme.try_map(|path| -> Result<RgbaImage, Box<dyn Error>> {
             use image::io::Reader;
-            Ok(Reader::open(prompt_root.join(s))?.decode()?.into_rgba8())
+            let load = || -> Result<RgbaImage, Box<dyn Error>> {
+                Ok(Reader::open(&path)?.decode()?.into_rgba8())
+            };
+            load().map_err(|e| format!("Failed to load {}: {e}", path.display()).into())
+        })?;
+        let layout = config
+            .image
+            .as_ref()
+            .map_or(ImageLayout::default(), |settings| settings.layout);
+        let compositor = match config.image.as_ref().and_then(|cfg| cfg.template.as_ref()) {
+            Some(template) => Compositor::from_template(
+                &config_dir.join(template),
+                layout,
+                theme.font.as_deref(),
+                &config.event.names(),
+            )?,
+            None => Compositor::Layout(layout),
         };
-        let prompts = [
-            load_image("prompts/prompts.001.png")?,
-            load_image("prompts/prompts.002.png")?,
-            load_image("prompts/prompts.003.png")?,
-            load_image("prompts/prompts.004.png")?,
-            load_image("prompts/prompts.005.png")?,
-            load_image("prompts/prompts.006.png")?,
-            load_image("prompts/prompts.007.png")?,
-        ];
+        let font = theme.font.as_deref().map(load_font).transpose()?;
 
         Ok(Self {
             config,
             path: path.as_ref().into(),
-            prompts,
+            theme,
+            font,
+            compositor,
         })
     }
 
-    pub fn build<T>(self, texture_creator: &TextureCreator<T>) -> crate::Result<Context<T>> {
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The config file the booth was set up from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the theme onto the GPU and opens the camera. `window_size` is the size of the
+    /// window the booth is shown in, before any display rotation.
+    pub fn build<T>(
+        self,
+        texture_creator: &TextureCreator<T>,
+        window_size: (u32, u32),
+    ) -> crate::Result<Context<T>> {
         let Self {
             config,
             path,
-            prompts: [prompt01, prompt02, prompt03, prompt04, prompt05, prompt06, prompt07],
+            theme,
+            font,
+            compositor,
         } = self;
+        let session_number = saved_image_count(&path, &config) + 1;
+        let prints_made = match &config.copies {
+            Some(copies) => read_print_count(&quota_path(&path, &copies.quota_file))?,
+            None => 0,
+        };
+        let screen = Screen::new(window_size, config.orientation.display_rotation);
         Ok(Context {
+            camera: Camera::new(
+                texture_creator,
+                config.video_source.clone(),
+                config.capture_source.clone(),
+                config.capture_command.clone(),
+            ),
             config,
             path,
             texture_creator,
-            prompt01: image_to_texture(prompt01, texture_creator)?,
-            prompt02: image_to_texture(prompt02, texture_creator)?,
-            prompt03: image_to_texture(prompt03, texture_creator)?,
-            prompt04: image_to_texture(prompt04, texture_creator)?,
-            prompt05: image_to_texture(prompt05, texture_creator)?,
-            prompt06: image_to_texture(prompt06, texture_creator)?,
-            prompt07: image_to_texture(prompt07, texture_creator)?,
+            theme: theme.try_map(|image| image_to_texture(image, texture_creator))?,
+            compositor: Arc::new(compositor),
+            session_number,
+            prints_made,
+            screen,
+            font,
+            print_job: None,
+            reprints: vec![],
+            pressed: None,
+            touch: TouchTracker::default(),
+            gpio_outputs: None,
+            serial: None,
+            paused: false,
+            last_saved: None,
         })
     }
 }
 
+/// The number of images already saved with the configured prefix, so session numbers carry on
+/// from where they left off when the booth is restarted.
+fn saved_image_count(config_path: &Path, config: &Config) -> u32 {
+    let prefix = (config.image.as_ref())
+        .and_then(|img| img.prefix.as_ref())
+        .map_or("", |s| s.as_str());
+    let mut pattern = PathBuf::from(config_path);
+    pattern.pop();
+    pattern.push(format!("{prefix}img_"));
+    let (Some(dir), Some(start)) = (pattern.parent(), pattern.file_name()) else {
+        return 0;
+    };
+    let start = start.to_string_lossy();
+    let Ok(entries) = dir.read_dir() else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&*start))
+        .count() as u32
+}
+
+/// The file counting prints towards the event's quota, relative to the config file.
+fn quota_path(config_path: &Path, quota_file: &str) -> PathBuf {
+    let mut path = PathBuf::from(config_path);
+    path.pop();
+    path.push(quota_file);
+    path
+}
+
+/// The number of prints counted in `path`, or 0 if nothing has been printed yet.
+fn read_print_count(path: &Path) -> crate::Result<u32> {
+    match std::fs::read_to_string(path) {
+        Ok(count) => Ok(count.trim().parse().map_err(|e| {
+            format!(
+                "{} should hold the number of prints made, but: {e}",
+                path.display()
+            )
+        })?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(format!("Failed to read {}: {e}", path.display()).into()),
+    }
+}
+
 pub struct Context<'t, T> {
     pub config: Config,
     pub path: PathBuf,
     pub texture_creator: &'t TextureCreator<T>,
-    pub prompt01: Texture<'t>,
-    pub prompt02: Texture<'t>,
-    pub prompt03: Texture<'t>,
-    pub prompt04: Texture<'t>,
-    pub prompt05: Texture<'t>,
-    pub prompt06: Texture<'t>,
-    pub prompt07: Texture<'t>,
+    pub camera: Camera<'t, T>,
+    pub theme: Theme<Texture<'t>>,
+    /// Shared with the threads that save each session's photos.
+    pub compositor: Arc<Compositor>,
+    /// The number of the next session to be saved, counting from 1.
+    pub session_number: u32,
+    /// Prints made towards the event's quota, as counted in the quota file.
+    pub prints_made: u32,
+    pub screen: Screen,
+    /// The theme's font, for writing messages on screen.
+    pub font: Option<FontArc>,
+    /// The most recent print job, if printing is configured.
+    pub print_job: Option<PrintJob>,
+    /// Reprints asked for through the control socket, with their copies, until the printer
+    /// takes them and they're counted towards the event's quota.
+    pub reprints: Vec<(PrintJob, u32)>,
+    /// The button being held down, if any.
+    pub pressed: Option<ButtonId>,
+    pub touch: TouchTracker,
+    /// The lights and flashes on the GPIO pins, once they're set up.
+    pub gpio_outputs: Option<GpioOutputs>,
+    /// Where the `[serial]` controller is told what the booth is doing, once it's set up.
+    pub serial: Option<SerialPort>,
+    /// Whether the operator has paused the booth through the control socket.
+    pub paused: bool,
+    /// The image of the last session saved since the booth started, for reprinting.
+    pub last_saved: Option<PathBuf>,
+}
+
+impl<'t, T> Context<'t, T> {
+    /// The values for text on the saved image of a session finishing at `now`.
+    pub fn text_values(&self, now: OffsetDateTime) -> crate::Result<HashMap<String, String>> {
+        let event = &self.config.event;
+        let date_format = time::format_description::parse_borrowed::<1>(&event.date_format)?;
+        let mut values = event.captions.clone();
+        values.insert("event_name".into(), event.name.clone());
+        values.insert("date".into(), now.format(&date_format)?);
+        values.insert("session_number".into(), self.session_number.to_string());
+        Ok(values)
+    }
+
+    /// The most prints that can be made of the next session, within the limits for each
+    /// session and for the event, leaving room for the reprints still being sent.
+    pub fn copies_allowed(&self) -> u32 {
+        let Some(copies) = &self.config.copies else {
+            return 1;
+        };
+        let sending: u32 = self.reprints.iter().map(|(_, copies)| copies).sum();
+        let remaining = copies.event_quota.map_or(u32::MAX, |quota| {
+            quota.saturating_sub(self.prints_made + sending)
+        });
+        copies.max_per_session.min(remaining)
+    }
+
+    /// Counts `copies` prints towards the event's quota, saving the count so it survives a
+    /// restart.
+    pub fn record_prints(&mut self, copies: u32) {
+        let Some(settings) = &self.config.copies else {
+            return;
+        };
+        if copies == 0 {
+            return;
+        }
+        self.prints_made += copies;
+        let path = quota_path(&self.path, &settings.quota_file);
+        if let Err(e) = std::fs::write(&path, format!("{}\n", self.prints_made)) {
+            eprintln!("Failed to save the print count to {}: {e}", path.display());
+        }
+    }
+
+    /// Counts the reprints the printer has taken towards the event's quota, and forgets those
+    /// that failed before it could.
+    pub fn count_reprints(&mut self) {
+        let mut taken = 0;
+        self.reprints.retain(|(print_job, copies)| {
+            if print_job.accepted() {
+                taken += copies;
+                return false;
+            }
+            !matches!(print_job.status(), PrintStatus::Failed(_))
+        });
+        self.record_prints(taken);
+    }
+
+    /// Reads the config file and theme again, keeping the print job, the screen's rotation,
+    /// and the inputs and outputs that were set up from the old config. The camera is opened
+    /// afresh, so it mustn't be in use.
+    pub fn reload(&mut self) -> crate::Result<()> {
+        let builder = ContextBuilder::from_file(&self.path)?;
+        let mut context = builder.build(self.texture_creator, self.screen.window_size())?;
+        context.screen = self.screen;
+        context.print_job = self.print_job.take();
+        context.reprints = std::mem::take(&mut self.reprints);
+        context.gpio_outputs = self.gpio_outputs.take();
+        context.serial = self.serial.take();
+        context.paused = self.paused;
+        context.last_saved = self.last_saved.take();
+        *self = context;
+        Ok(())
+    }
+
+    /// Sets the GPIO outputs and the serial controller's lights for the booth's `phase`, and
+    /// the `shot` being taken, if any.
+    pub fn update_outputs(&mut self, phase: Phase, shot: Option<usize>, now: std::time::Instant) {
+        self.with_outputs(|outputs| outputs.update(phase, now));
+        // The controller is reconnected by the thread reading it, so it's only reported the
+        // first time it fails rather than as it's retried every frame.
+        if let Some(Err(e)) = self
+            .serial
+            .as_ref()
+            .map(|serial| serial.report(phase, shot))
+        {
+            if e.kind() != std::io::ErrorKind::NotConnected {
+                eprintln!("Failed to tell the serial controller the booth's state: {e}");
+            }
+        }
+    }
+
+    /// Fires the flashes on the GPIO outputs, as a photo is taken.
+    pub fn flash(&mut self, now: std::time::Instant) {
+        self.with_outputs(|outputs| outputs.flash(now));
+    }
+
+    /// A broken light shouldn't stop the booth, so the outputs are given up on if they fail.
+    fn with_outputs(&mut self, f: impl FnOnce(&mut GpioOutputs) -> crate::Result<()>) {
+        if let Some(Err(e)) = self.gpio_outputs.as_mut().map(f) {
+            eprintln!("GPIO outputs stopped: {e}");
+            self.gpio_outputs = None;
+        }
+    }
 }
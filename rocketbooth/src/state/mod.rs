@@ -3,147 +3,249 @@ mod context;
 use std::{
     path::PathBuf,
     process::Command,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
     thread::JoinHandle,
     time::{Duration, Instant},
 };
 
-use image::RgbImage;
+use image::{RgbImage, RgbaImage};
 use sdl2::{
     event::{Event, EventPollIterator},
     keyboard::Keycode,
     mouse::MouseButton,
     pixels::Color,
-    rect::Rect,
+    rect::{Point, Rect},
     render::{Canvas, RenderTarget, Texture},
 };
 use time::format_description::BorrowedFormatItem;
 use time::macros::format_description;
 use time::OffsetDateTime;
 
-use crate::{config::ImageLayout, image_libav::frame_to_image, libav_sdl2::FrameTextureManager};
+use crate::{
+    config::{Action, ImageLayout, Phase, ReviewAction, Slot},
+    control::{ControlCommand, ControlRequest, Reply, Status},
+    framing::{rotated_size, Framing},
+    image_sdl2::image_to_texture,
+    input::InputEvent,
+    print::{print_and_follow, PrintJob, PrinterProblem},
+    text::{draw_text, Align, TextField},
+    theme::{place, Anchor, Scale},
+    touch::{Gesture, SwipeDirection, TOUCH_MOUSE_ID},
+    video_input::ConnectionStatus,
+    widget::{button_at, place_buttons, render_button, Button, ButtonId, OperatorChoice},
+};
 
 pub use self::context::{Context, ContextBuilder};
 
 const FILE_TIMESTAMP_FORMAT: &[BorrowedFormatItem] =
     format_description!("[year]-[month]-[day]_[hour]-[minute]-[second]");
 
-pub enum State<'t, T> {
+pub enum State<'t> {
     Waiting,
     Welcome {
         deadline: Instant,
     },
     Explainer {
-        frame_texture_manager: FrameTextureManager<'t, T>,
         deadline: Instant,
     },
     Capture {
         captured_textures: Vec<Texture<'t>>,
-        captured_images: Vec<RgbImage>,
-        frame_texture_manager: FrameTextureManager<'t, T>,
+        captured_images: Vec<JoinHandle<RgbImage>>,
+        deadline: Instant,
+        /// The shot being taken again from the review screen, if any; otherwise the next one.
+        retake: Option<usize>,
+    },
+    /// Showing the photos before they're saved, so the guest can keep them or take them again.
+    Review {
+        captured_textures: Vec<Texture<'t>>,
+        captured_images: Vec<JoinHandle<RgbImage>>,
+        deadline: Instant,
+        /// The photo picked to be retaken, if any.
+        selected: Option<usize>,
+        /// The writing on each button, if the theme has a font.
+        labels: Vec<Texture<'t>>,
+    },
+    /// Asking how many prints to make, while the session's image is saved.
+    Copies {
+        captured_textures: Vec<Texture<'t>>,
         deadline: Instant,
+        image_saving_handle: JoinHandle<()>,
+        order: PrintOrder,
+        /// The buttons offer from 1 to this many copies.
+        choices: u32,
+        default: u32,
+        /// The number on each button, if the theme has a font.
+        labels: Vec<Texture<'t>>,
     },
     Debrief {
         captured_textures: Vec<Texture<'t>>,
         deadline: Instant,
         image_saving_handle: JoinHandle<()>,
     },
+    /// The printer needs attention, eg it's out of paper.
+    PrinterError {
+        problems: Vec<PrinterProblem>,
+        /// The problems written out, if the theme has a font.
+        message: Option<Texture<'t>>,
+    },
+    /// The operator's menu, opened by holding a finger on the screen while the booth is idle.
+    Operator {
+        deadline: Instant,
+        /// The writing on each button, if the theme has a font.
+        labels: Vec<Texture<'t>>,
+    },
 }
 
-impl<'t, T> Default for State<'t, T> {
+/// The thread saving a session waits on this to hear how many copies to print.
+pub struct PrintOrder {
+    copies: Sender<u32>,
+    print_job: Option<PrintJob>,
+}
+
+impl PrintOrder {
+    /// Counts the copies towards the quota and lets the saving thread print them.
+    fn send<T>(self, context: &mut Context<T>, copies: u32) {
+        context.record_prints(copies);
+        if copies > 0 && self.print_job.is_some() {
+            context.print_job = self.print_job;
+        }
+        // The thread only goes away early if saving failed, which it has already reported.
+        let _ = self.copies.send(copies);
+    }
+}
+
+impl<'t> Default for State<'t> {
     fn default() -> Self {
         Self::Waiting
     }
 }
 
-impl<'t, T> State<'t, T> {
-    pub fn handle_event(
+impl<'t> State<'t> {
+    pub fn handle_event<T>(
         self,
         events: EventPollIterator,
         context: &mut Context<'t, T>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let now = std::time::Instant::now();
+        let timing = context.config.timing.clone();
+        context.camera.update(now);
+        context.update_outputs(self.phase(), self.shot(), now);
+        context.count_reprints();
 
         for event in events {
+            if let Some(request) = event.as_user_event_type::<ControlRequest>() {
+                return self.control(request, now, context);
+            }
+            // A paused booth waits on the idle screen for the operator, ignoring its guests.
+            if context.paused
+                && matches!(self, State::Waiting)
+                && !matches!(event, Event::Quit { .. })
+            {
+                // Frees any input sent from outside SDL.
+                let _ = event.as_user_event_type::<InputEvent>();
+                continue;
+            }
+            let touch = &context.config.controls.touch;
+            if let Some(gesture) = context.touch.track(&event, now, touch, &context.screen) {
+                return self.gesture(gesture, now, context);
+            }
+            // Inputs from outside SDL, eg the GPIO pins, are sent as custom events. Any others
+            // are left alone.
+            if let Some(input) = event.as_user_event_type::<InputEvent>() {
+                match input.action(&context.config.controls.bindings) {
+                    Some(action) => return self.act(action, now, context),
+                    None => continue,
+                }
+            }
             match event {
-                Event::KeyDown {
-                    keycode: Some(Keycode::Backspace),
+                Event::Quit { .. } => return self.act(Action::Shutdown, now, context),
+                // SDL also reports touches as clicks of a made up mouse, which would press
+                // everything twice.
+                Event::MouseButtonDown {
+                    which: TOUCH_MOUSE_ID,
                     ..
-                } => {
-                    if context.config.controls.enable_backspace_command
-                        && !context.config.controls.backspace_command.is_empty()
-                    {
-                        Command::new(&context.config.controls.backspace_command[0])
-                            .args(&context.config.controls.backspace_command[1..])
-                            .output()
-                            .unwrap();
-                    }
                 }
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape | Keycode::Q),
+                | Event::MouseButtonUp {
+                    which: TOUCH_MOUSE_ID,
+                    ..
+                } => {}
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
                     ..
                 } => {
-                    if context.config.controls.enable_exit_inputs {
-                        return Result::Err("Shutdown".into());
-                    }
+                    let point = context.screen.to_screen(Point::new(x, y));
+                    return self.tap_at(point, now, context);
                 }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Return | Keycode::Space),
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
                     ..
+                } if context.pressed.is_some() => {
+                    let point = context.screen.to_screen(Point::new(x, y));
+                    return self.release_at(point, now, context);
                 }
-                | Event::MouseButtonDown {
-                    mouse_btn: MouseButton::Left,
+                Event::KeyDown {
+                    keycode: Some(keycode),
                     ..
+                } if self.takes_key(keycode) => {
+                    return self.press(keycode, now, context);
                 }
-                | Event::User { .. } => {
-                    return Ok(match self {
-                        State::Waiting => State::Welcome {
-                            deadline: now + Duration::from_secs(30),
-                        },
-                        State::Welcome { .. } => State::Explainer {
-                            frame_texture_manager: FrameTextureManager::new(
-                                &context.config.video_source,
-                                context.texture_creator,
-                            )?,
-                            deadline: now + Duration::from_secs(30),
-                        },
-                        State::Explainer {
-                            frame_texture_manager,
-                            ..
-                        } => State::Capture {
-                            frame_texture_manager,
-                            deadline: now + Duration::from_secs(4),
-                            captured_images: vec![],
-                            captured_textures: vec![],
-                        },
-                        x @ State::Capture { .. } => x,
-                        State::Debrief { .. } => State::Welcome {
-                            deadline: Instant::now() + Duration::from_secs(5),
-                        },
-                    })
+                // Held keys repeat, which would skip through the screens.
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(action) = context.config.controls.bindings.key(keycode) {
+                        return self.act(action, now, context);
+                    }
                 }
                 _ => {}
             }
         }
+        if let Some(gesture) = context
+            .touch
+            .long_press(now, &context.config.controls.touch)
+        {
+            return self.gesture(gesture, now, context);
+        }
 
         Ok(match self {
             State::Welcome { deadline } | State::Explainer { deadline, .. } if deadline < now => {
+                context.camera.release();
                 State::Waiting
             }
+            // Don't capture a stale frame while the camera is away; restart the countdown instead.
+            State::Capture {
+                deadline,
+                captured_images,
+                captured_textures,
+                retake,
+            } if deadline < now && context.camera.status() != ConnectionStatus::Live => {
+                State::Capture {
+                    deadline: now + seconds(timing.countdown),
+                    captured_images,
+                    captured_textures,
+                    retake,
+                }
+            }
             State::Capture {
                 deadline,
-                mut frame_texture_manager,
                 mut captured_images,
                 mut captured_textures,
+                retake,
             } if deadline < now => {
-                let image = {
-                    let frame = frame_texture_manager
-                        .frame_ref()
-                        .ok_or("Trying to capture before device is ready")?;
-                    frame_to_image(frame)?
-                };
+                context.flash(now);
+                let image = context.camera.capture_still()?;
                 let texture = {
-                    let texture = frame_texture_manager
+                    let texture = context
+                        .camera
                         .texture_mut()
                         .ok_or("Texture not ready yet")?;
                     let query = texture.query();
@@ -155,101 +257,86 @@ impl<'t, T> State<'t, T> {
                     std::mem::swap(texture, &mut new_texture);
                     new_texture
                 };
-                captured_images.push(image);
-                captured_textures.push(texture);
+                match retake {
+                    Some(shot) => {
+                        captured_images[shot] = image;
+                        captured_textures[shot] = texture;
+                    }
+                    None => {
+                        captured_images.push(image);
+                        captured_textures.push(texture);
+                    }
+                }
 
-                if captured_images.len()
-                    < (context.config.image.as_ref())
-                        .map_or(ImageLayout::default(), |cfg| cfg.layout)
-                        .capture_count()
+                if retake.is_none()
+                    && captured_images.len()
+                        < (context.config.image.as_ref())
+                            .map_or(ImageLayout::default(), |cfg| cfg.layout)
+                            .capture_count()
                 {
                     State::Capture {
-                        deadline: deadline + Duration::from_secs(4),
-                        frame_texture_manager,
+                        deadline: deadline + seconds(timing.shot_gap) + seconds(timing.countdown),
                         captured_images,
                         captured_textures,
+                        retake: None,
                     }
+                } else if context.config.review.is_some() {
+                    review(context, captured_images, captured_textures, now)?
                 } else {
-                    let post_command = context.config.image.as_ref().and_then(|cfg| {
-                        if cfg.enable_post_command {
-                            cfg.post_command.as_ref().filter(|v| !v.is_empty()).cloned()
-                        } else {
-                            None
-                        }
-                    });
-                    let layout = context
-                        .config
-                        .image
-                        .as_ref()
-                        .map_or(ImageLayout::default(), |settings| settings.layout);
-                    let (width, height) =
-                        layout.dest_size(captured_images[0].width(), captured_images[0].height());
-
-                    let prefix = (context.config.image.as_ref())
-                        .and_then(|img| img.prefix.as_ref())
-                        .map_or("", |s| s.as_str());
-                    let format = (context.config.image.as_ref())
-                        .and_then(|cfg| cfg.format.as_ref())
-                        .map_or("", |s| s.as_str());
-                    let format = if format == "PNG" {
-                        image::ImageFormat::Png
-                    } else {
-                        image::ImageFormat::Jpeg
-                    };
-                    let suffix = if format == image::ImageFormat::Png {
-                        "png"
-                    } else {
-                        "jpeg"
-                    };
-                    let timestamp = OffsetDateTime::now_local()
-                        .unwrap_or_else(|_| OffsetDateTime::now_utc())
-                        .format(FILE_TIMESTAMP_FORMAT)?;
-                    let mut saved_path = PathBuf::from(&context.path);
-                    saved_path.pop();
-                    saved_path.push(format!("{prefix}img_{timestamp}.{suffix}"));
-                    eprintln!("{}", saved_path.display());
-                    let saved_path = saved_path;
-                    let image_saving_handle = std::thread::spawn(move || {
-                        let mut final_image = RgbImage::new(width, height);
-                        for (&(x, y, _, _), partial_image) in Iterator::zip(
-                            layout.arrange_within_rect(width, height).iter(),
-                            captured_images.iter(),
-                        ) {
-                            image::imageops::overlay(
-                                &mut final_image,
-                                partial_image,
-                                x as i64,
-                                y as i64,
-                            );
-                        }
-                        final_image.save_with_format(&saved_path, format).unwrap();
-                        if let Some(post_command) = post_command {
-                            let _ = Command::new(&post_command[0])
-                                .args(&post_command[1..])
-                                .arg(saved_path)
-                                .output()
-                                .unwrap();
-                        }
-                    });
-                    State::Debrief {
-                        captured_textures,
-                        deadline: deadline + Duration::from_secs(5),
-                        image_saving_handle,
-                    }
+                    finish_session(context, captured_images, captured_textures, deadline)?
                 }
             }
+            State::Review { deadline, .. } if deadline < now => {
+                let action = (context.config.review.as_ref())
+                    .map_or(ReviewAction::default(), |review| review.timeout_action);
+                self.review_action(action, now, context)?
+            }
+            State::Copies {
+                deadline, default, ..
+            } if deadline < now => self.choose_copies(default, now, context)?,
+            State::Operator { deadline, .. } if deadline < now => State::Waiting,
             State::Debrief {
                 deadline,
                 image_saving_handle,
                 ..
-            } if deadline < now && image_saving_handle.is_finished() => State::Welcome {
-                deadline: deadline + Duration::from_secs(30),
-            },
+            } if deadline < now && image_saving_handle.is_finished() => {
+                let problems = printer_problems(context);
+                if problems.is_empty() && context.paused {
+                    context.camera.release();
+                    State::Waiting
+                } else if problems.is_empty() {
+                    State::Welcome {
+                        deadline: deadline + seconds(timing.welcome_after_session),
+                    }
+                } else {
+                    printer_error(context, problems)?
+                }
+            }
+            State::Waiting => {
+                let problems = printer_problems(context);
+                if problems.is_empty() {
+                    self
+                } else {
+                    printer_error(context, problems)?
+                }
+            }
+            State::PrinterError { ref problems, .. } => {
+                let current = printer_problems(context);
+                if current.is_empty() {
+                    State::Waiting
+                } else if current == *problems {
+                    self
+                } else {
+                    printer_error(context, current)?
+                }
+            }
             _ => self,
         })
     }
 
-    pub fn render<U>(
+    /// Draws the current screen over the whole of the canvas's viewport, leaving the caller to
+    /// present it.
+    pub fn render<T, U>(
         &mut self,
         canvas: &mut Canvas<U>,
         context: &mut Context<T>,
@@ -257,88 +344,1507 @@ impl<'t, T> State<'t, T> {
     where
         U: RenderTarget,
     {
+        let buttons = self.buttons(context);
         match self {
             State::Waiting => {
                 canvas.set_draw_color(Color::BLACK);
                 canvas.clear();
-                canvas.present();
+                if let Some(idle) = &context.theme.idle {
+                    idle.render(canvas, None)?;
+                }
             }
             State::Welcome { .. } => {
                 canvas.clear();
-                canvas.copy(&context.prompt01, None, None)?;
-                canvas.present();
+                context.theme.welcome.render(canvas, None)?;
             }
-            State::Explainer {
-                frame_texture_manager,
-                ..
-            } => {
+            State::Explainer { .. } => {
                 canvas.clear();
-                if let Some(texture) = frame_texture_manager.texture_ref() {
-                    canvas.copy_ex(texture, None, None, 0f64, None, true, false)?;
+                if context.camera.status() == ConnectionStatus::Lost {
+                    render_reconnecting(canvas, context, None)?;
+                } else {
+                    let framing = (context.config.image.as_ref())
+                        .map_or(Framing::default(), |cfg| cfg.framing);
+                    let orientation = context.config.orientation;
+                    if let Some(texture) = context.camera.texture_ref() {
+                        let query = texture.query();
+                        let (width, height) =
+                            rotated_size(query.width, query.height, orientation.rotation);
+                        let (width, height) = framing.framed_size(width, height);
+                        let dest =
+                            place(width, height, Scale::Fit, Anchor::Center, canvas.viewport());
+                        framing.render(
+                            canvas,
+                            texture,
+                            dest,
+                            orientation.rotation,
+                            orientation.mirror_preview,
+                        )?;
+                    }
+                    context.theme.explainer.render(canvas, None)?;
                 }
-                canvas.copy(&context.prompt02, None, None)?;
-                canvas.present();
             }
             State::Capture {
-                frame_texture_manager,
                 captured_textures,
                 deadline,
+                retake,
                 ..
             } => {
-                let t = *deadline - Instant::now();
-                let countdown_overlay = match t.as_secs() {
-                    0 => Some(&context.prompt06),
-                    1 => Some(&context.prompt05),
-                    2 => Some(&context.prompt04),
-                    3 => Some(&context.prompt03),
-                    _ => None,
-                };
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let is_live = context.camera.status() == ConnectionStatus::Live;
+                let countdown_overlay = countdown_step(
+                    remaining,
+                    seconds(context.config.timing.countdown),
+                    context.theme.countdown.len(),
+                )
+                .filter(|_| is_live)
+                .map(|step| &context.theme.countdown[step]);
                 let layout = (context.config.image.as_ref())
                     .map_or(ImageLayout::default(), |cfg| cfg.layout);
-                let (width, height) = canvas.output_size()?;
-                canvas.clear();
+                let framing =
+                    (context.config.image.as_ref()).map_or(Framing::default(), |cfg| cfg.framing);
+                let orientation = context.config.orientation;
+                let frame = captured_textures.first().or(context.camera.texture_ref());
+                let area = layout_area(
+                    canvas.viewport(),
+                    layout,
+                    framing,
+                    orientation.rotation,
+                    frame,
+                );
+                clear_to_background(canvas, layout, area)?;
 
-                let rects = layout.arrange_within_rect(width, height);
-                for (&(x, y, w, h), tex) in Iterator::zip(rects.iter(), captured_textures.iter()) {
-                    let rect = Some(Rect::new(x as i32, y as i32, w, h));
-                    canvas.copy_ex(tex, None, rect, 0f64, None, true, false)?;
-                }
-                if let Some(&(x, y, w, h)) = rects.get(captured_textures.len()) {
-                    let rect = Some(Rect::new(x as i32, y as i32, w, h));
-                    if let Some(tex) = frame_texture_manager.texture_ref() {
-                        canvas.copy_ex(tex, None, rect, 0f64, None, true, false)?;
-                    }
-                    if let Some(overlay) = countdown_overlay {
-                        canvas.copy(overlay, None, rect)?;
+                let live_shot = retake.unwrap_or(captured_textures.len());
+                for slot in layout.slots(area.width(), area.height()) {
+                    let rect = slot_rect(&slot, area);
+                    if slot.shot == live_shot {
+                        if context.camera.status() == ConnectionStatus::Lost {
+                            render_reconnecting(canvas, context, Some(rect))?;
+                        } else if let Some(tex) = context.camera.texture_ref() {
+                            framing.render(
+                                canvas,
+                                tex,
+                                rect,
+                                orientation.rotation,
+                                orientation.mirror_preview,
+                            )?;
+                        }
+                        if let Some(overlay) = countdown_overlay {
+                            overlay.render(canvas, Some(rect))?;
+                        }
+                    } else if let Some(tex) = captured_textures.get(slot.shot) {
+                        framing.render(
+                            canvas,
+                            tex,
+                            rect,
+                            orientation.rotation,
+                            orientation.mirror_review,
+                        )?;
                     }
                 }
-                if let Some(overlay) = countdown_overlay {
-                    let rect = rects
-                        .get(captured_textures.len())
-                        .map(|&(x, y, w, h)| Rect::new(x as i32, y as i32, w, h));
-                    if rect.is_some() {
-                        canvas.copy(overlay, None, rect)?;
-                    }
+            }
+            State::Review {
+                captured_textures,
+                selected,
+                labels,
+                ..
+            } => {
+                let (photos, _) = review_layout(context.screen.size());
+                render_photos(canvas, context, captured_textures, photos, *selected)?;
+                if let Some(review) = &context.theme.review {
+                    review.render(canvas, None)?;
+                }
+                for (i, button) in buttons.into_iter().enumerate() {
+                    let label = labels.get(i);
+                    let ButtonId::Review(action) = button.id else {
+                        continue;
+                    };
+                    let [r, g, b] = match action {
+                        ReviewAction::RetakeShot if selected.is_none() => [96, 96, 96],
+                        _ => review_color(action),
+                    };
+                    render_button(
+                        canvas,
+                        context.theme.buttons.get(&button.id.name()),
+                        button,
+                        context.pressed == Some(button.id),
+                        |canvas, rect| {
+                            canvas.set_draw_color(Color::RGB(r, g, b));
+                            canvas.fill_rect(rect)?;
+                            if let Some(label) = label {
+                                canvas.copy(label, None, rect)?;
+                            }
+                            canvas.set_draw_color(Color::BLACK);
+                            Ok(())
+                        },
+                    )?;
+                }
+            }
+            State::Copies {
+                captured_textures,
+                labels,
+                ..
+            } => {
+                render_photos(canvas, context, captured_textures, canvas.viewport(), None)?;
+                if let Some(copies) = &context.theme.copies {
+                    copies.render(canvas, None)?;
+                }
+                for (i, button) in buttons.into_iter().enumerate() {
+                    let label = labels.get(i);
+                    let ButtonId::Copies(copies) = button.id else {
+                        continue;
+                    };
+                    render_button(
+                        canvas,
+                        context.theme.buttons.get(&button.id.name()),
+                        button,
+                        context.pressed == Some(button.id),
+                        |canvas, rect| {
+                            canvas.set_draw_color(Color::WHITE);
+                            canvas.fill_rect(rect)?;
+                            match label {
+                                Some(label) => canvas.copy(label, None, rect)?,
+                                None => {
+                                    canvas.set_draw_color(Color::BLACK);
+                                    canvas.fill_rects(&pips(rect, copies))?;
+                                }
+                            }
+                            canvas.set_draw_color(Color::BLACK);
+                            Ok(())
+                        },
+                    )?;
                 }
-                canvas.present();
             }
             State::Debrief {
-                captured_textures, ..
+                captured_textures,
+                image_saving_handle,
+                ..
             } => {
-                let layout = (context.config.image.as_ref())
-                    .map_or(ImageLayout::default(), |cfg| cfg.layout);
-                let (width, height) = canvas.output_size()?;
+                render_photos(canvas, context, captured_textures, canvas.viewport(), None)?;
+                let overlay = match &context.theme.thank_you {
+                    Some(thank_you) if image_saving_handle.is_finished() => thank_you,
+                    _ => &context.theme.processing,
+                };
+                overlay.render(canvas, None)?;
+            }
+            State::PrinterError { message, .. } => {
+                canvas.set_draw_color(Color::BLACK);
                 canvas.clear();
-                for (&(x, y, w, h), tex) in Iterator::zip(
-                    layout.arrange_within_rect(width, height).iter(),
-                    captured_textures.iter(),
-                ) {
-                    canvas.copy(tex, None, Some(Rect::new(x as i32, y as i32, w, h)))?;
+                match &context.theme.printer_error {
+                    Some(printer_error) => printer_error.render(canvas, None)?,
+                    None => render_reconnecting(canvas, context, None)?,
+                }
+                if let Some(message) = message {
+                    let query = message.query();
+                    let dest = place(
+                        query.width,
+                        query.height,
+                        Scale::Original,
+                        Anchor::Bottom,
+                        canvas.viewport(),
+                    );
+                    canvas.copy(message, None, dest)?;
+                }
+            }
+            State::Operator { labels, .. } => {
+                canvas.set_draw_color(Color::BLACK);
+                canvas.clear();
+                for (i, button) in buttons.into_iter().enumerate() {
+                    let label = labels.get(i);
+                    let [r, g, b] = match button.id {
+                        ButtonId::Operator(OperatorChoice::Quit) => [190, 40, 40],
+                        _ => [70, 70, 70],
+                    };
+                    render_button(
+                        canvas,
+                        context.theme.buttons.get(&button.id.name()),
+                        button,
+                        context.pressed == Some(button.id),
+                        |canvas, rect| {
+                            canvas.set_draw_color(Color::RGB(r, g, b));
+                            canvas.fill_rect(rect)?;
+                            if let Some(label) = label {
+                                canvas.copy(label, None, rect)?;
+                            }
+                            canvas.set_draw_color(Color::BLACK);
+                            Ok(())
+                        },
+                    )?;
                 }
-                canvas.copy(&context.prompt07, None, None)?;
-                canvas.present();
             }
         }
         Ok(())
     }
 }
+
+impl<'t> State<'t> {
+    /// The stage of the session the current screen shows.
+    pub fn phase(&self) -> Phase {
+        match self {
+            State::Waiting => Phase::Idle,
+            State::Welcome { .. } => Phase::Welcome,
+            State::Explainer { .. } => Phase::Explainer,
+            State::Capture { .. } => Phase::Countdown,
+            State::Review { .. } => Phase::Review,
+            State::Copies { .. } => Phase::Copies,
+            State::Debrief { .. } => Phase::Debrief,
+            State::PrinterError { .. } => Phase::PrinterError,
+            State::Operator { .. } => Phase::Operator,
+        }
+    }
+
+    /// The photo of the session being counted down to, counting from 1.
+    pub fn shot(&self) -> Option<usize> {
+        match self {
+            State::Capture {
+                captured_textures,
+                retake,
+                ..
+            } => Some(retake.unwrap_or(captured_textures.len()) + 1),
+            _ => None,
+        }
+    }
+
+    /// How the booth is doing, as reported through the control socket.
+    pub fn status<T>(&self, context: &Context<T>) -> Status {
+        Status {
+            state: self.phase().state_name().into(),
+            shot: self.shot(),
+            paused: context.paused,
+            session_number: context.session_number,
+            prints_made: context.prints_made,
+            printer_problems: (printer_problems(context).iter())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+
+    /// The buttons the current screen shows, in the order they're drawn.
+    pub fn buttons<T>(&self, context: &Context<T>) -> Vec<Button> {
+        match self {
+            State::Review { .. } => review_buttons(context),
+            State::Copies { choices, .. } => copies_buttons(context, *choices),
+            State::Operator { .. } => operator_buttons(context),
+            _ => vec![],
+        }
+    }
+
+    /// Does what an input bound to `action` asks, as far as it makes sense on the current
+    /// screen.
+    fn act<T>(
+        self,
+        action: Action,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match action {
+            Action::Advance => self.advance(now, context),
+            Action::Cancel => self.cancel(now, context),
+            Action::Retake => self.review_action(ReviewAction::RetakeAll, now, context),
+            Action::Print => match self {
+                State::Copies { default, .. } => self.choose_copies(default, now, context),
+                _ => self.review_action(ReviewAction::Keep, now, context),
+            },
+            // Only while idle, so nothing leaves a guest's session half done.
+            Action::OperatorMenu
+                if matches!(
+                    self,
+                    State::Waiting | State::Welcome { .. } | State::PrinterError { .. }
+                ) =>
+            {
+                context.pressed = None;
+                context.camera.release();
+                operator_menu(context, now)
+            }
+            Action::OperatorMenu => Ok(self),
+            Action::RunCommand => {
+                run_backspace_command(context);
+                Ok(self)
+            }
+            Action::Shutdown if context.config.controls.enable_exit_inputs => {
+                Err("Shutdown".into())
+            }
+            Action::Shutdown => Ok(self),
+        }
+    }
+
+    /// Carries out a command from the control socket with `run_command`, replying to it.
+    fn control<T>(
+        self,
+        request: ControlRequest,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut state = self;
+        let reply = run_command(&mut state, context, request.command, now)?;
+        request.reply(reply);
+        if request.command == ControlCommand::Shutdown {
+            return Err("Shutdown".into());
+        }
+        Ok(state)
+    }
+
+    /// Handles a click or tap at `point`: the bound regions come first, then the buttons on the
+    /// screens with them, which act when they're let go so guests can see them pressed.
+    fn tap_at<T>(
+        self,
+        point: Point,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let bindings = &context.config.controls.bindings;
+        if let Some(action) = bindings.region(point, context.screen.size()) {
+            self.act(action, now, context)
+        } else if !self.buttons(context).is_empty() {
+            Ok(self.press_at(point, context))
+        } else {
+            self.act(bindings.tap, now, context)
+        }
+    }
+
+    /// Moves on to the next screen, as when the guest presses the big button.
+    fn advance<T>(
+        self,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let timing = context.config.timing.clone();
+        Ok(match self {
+            State::Waiting => {
+                // Start the camera now so it's ready by the time the preview shows.
+                context.camera.acquire()?;
+                State::Welcome {
+                    deadline: now + seconds(timing.welcome_timeout),
+                }
+            }
+            State::Welcome { .. } => {
+                context.camera.acquire()?;
+                State::Explainer {
+                    deadline: now + seconds(timing.explainer_timeout),
+                }
+            }
+            State::Explainer { .. } => State::Capture {
+                deadline: now + seconds(timing.countdown),
+                captured_images: vec![],
+                captured_textures: vec![],
+                retake: None,
+            },
+            x @ State::Capture { .. } => x,
+            State::Review { .. } => self.review_action(ReviewAction::Keep, now, context)?,
+            State::Copies { default, .. } => self.choose_copies(default, now, context)?,
+            State::Debrief { .. } => State::Welcome {
+                deadline: now + seconds(timing.welcome_after_skip),
+            },
+            State::PrinterError { .. } => {
+                // Printing carries on once the problem is fixed; until then, guests can keep
+                // taking photos.
+                if let Some(print_job) = &context.print_job {
+                    print_job.dismiss();
+                }
+                State::Welcome {
+                    deadline: now + seconds(timing.welcome_after_skip),
+                }
+            }
+            State::Operator { .. } => State::Waiting,
+        })
+    }
+
+    /// Gives up on the session in progress.
+    fn cancel<T>(
+        self,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(match self {
+            State::Welcome { .. }
+            | State::Explainer { .. }
+            | State::Capture { .. }
+            | State::Operator { .. } => {
+                context.camera.release();
+                State::Waiting
+            }
+            State::Review { .. } => self.review_action(ReviewAction::Discard, now, context)?,
+            // The image is saved already, but needn't be printed.
+            State::Copies { .. } => self.choose_copies(0, now, context)?,
+            State::PrinterError { .. } => {
+                if let Some(print_job) = &context.print_job {
+                    print_job.dismiss();
+                }
+                State::Waiting
+            }
+            State::Waiting | State::Debrief { .. } => self,
+        })
+    }
+
+    /// Handles what a finger did on the touch screen. A tap presses a button on the screens
+    /// with buttons, and moves on from the others.
+    fn gesture<T>(
+        self,
+        gesture: Gesture,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match gesture {
+            Gesture::Press(point) => self.tap_at(point, now, context),
+            Gesture::Release(point) => self.release_at(point, now, context),
+            Gesture::Swipe(direction) => {
+                context.pressed = None;
+                Ok(self.swipe(direction))
+            }
+            Gesture::LongPress(_) => self.act(Action::OperatorMenu, now, context),
+        }
+    }
+
+    /// Steps through the photos on the review screen: swiping left picks the next one, and
+    /// right the one before.
+    fn swipe(mut self, direction: SwipeDirection) -> Self {
+        if let State::Review {
+            captured_textures,
+            selected,
+            ..
+        } = &mut self
+        {
+            let last = captured_textures.len().saturating_sub(1);
+            *selected = Some(match (direction, *selected) {
+                (SwipeDirection::Left, Some(shot)) => (shot + 1).min(last),
+                (SwipeDirection::Left, None) => 0,
+                (SwipeDirection::Right, Some(shot)) => shot.saturating_sub(1),
+                (SwipeDirection::Right, None) => last,
+            });
+        }
+        self
+    }
+
+    /// Handles the start of a tap at `point` on a screen with buttons: a button is held down
+    /// until it's let go, and on the review screen, a tap on a photo picks it.
+    fn press_at<T>(mut self, point: Point, context: &mut Context<'t, T>) -> Self {
+        if let Some(id) = button_at(&self.buttons(context), point) {
+            context.pressed = Some(id);
+        } else if let State::Review {
+            captured_textures,
+            selected,
+            ..
+        } = &mut self
+        {
+            let (photos, _) = review_layout(context.screen.size());
+            let (_, rects) = photo_rects(context, captured_textures, photos);
+            if let Some((shot, _)) = rects
+                .into_iter()
+                .find(|(_, rect)| rect.contains_point(point))
+            {
+                *selected = Some(shot);
+            }
+        }
+        self
+    }
+
+    /// Handles the end of a tap at `point`, which presses the held button if it's still over
+    /// it.
+    fn release_at<T>(
+        self,
+        point: Point,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(pressed) = context.pressed.take() else {
+            return Ok(self);
+        };
+        if button_at(&self.buttons(context), point) != Some(pressed) {
+            return Ok(self);
+        }
+        match pressed {
+            ButtonId::Review(action) => self.review_action(action, now, context),
+            ButtonId::Copies(copies) => self.choose_copies(copies, now, context),
+            ButtonId::Operator(choice) => match choice {
+                OperatorChoice::Resume => Ok(State::Waiting),
+                OperatorChoice::RunCommand => {
+                    run_backspace_command(context);
+                    Ok(State::Waiting)
+                }
+                OperatorChoice::Quit => Err("Shutdown".into()),
+            },
+        }
+    }
+
+    /// Whether the current screen has a use for the key, eg a number on the copies screen.
+    fn takes_key(&self, keycode: Keycode) -> bool {
+        match self {
+            State::Copies { choices, .. } => {
+                digit(keycode).is_some_and(|copies| (1..=*choices).contains(&copies))
+            }
+            State::Review {
+                captured_textures, ..
+            } => digit(keycode)
+                .is_some_and(|shot| (1..=captured_textures.len() as u32).contains(&shot)),
+            _ => false,
+        }
+    }
+
+    /// Handles a key that `takes_key` accepted: on the copies screen, a number chooses that
+    /// many copies, and on the review screen, a number retakes that photo.
+    fn press<T>(
+        mut self,
+        keycode: Keycode,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let number = digit(keycode).unwrap_or(1);
+        match &mut self {
+            State::Copies { .. } => self.choose_copies(number, now, context),
+            State::Review { selected, .. } => {
+                *selected = Some(number as usize - 1);
+                self.review_action(ReviewAction::RetakeShot, now, context)
+            }
+            _ => Ok(self),
+        }
+    }
+
+    /// Leaves the review screen as the guest chose. Retaking a shot does nothing until one has
+    /// been picked.
+    fn review_action<T>(
+        self,
+        action: ReviewAction,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if action == ReviewAction::RetakeShot
+            && matches!(self, State::Review { selected: None, .. })
+        {
+            return Ok(self);
+        }
+        let State::Review {
+            captured_textures,
+            captured_images,
+            selected,
+            ..
+        } = self
+        else {
+            return Ok(self);
+        };
+        let countdown = now + seconds(context.config.timing.countdown);
+        Ok(match action {
+            ReviewAction::Keep => finish_session(context, captured_images, captured_textures, now)?,
+            ReviewAction::RetakeAll => State::Capture {
+                captured_textures: vec![],
+                captured_images: vec![],
+                deadline: countdown,
+                retake: None,
+            },
+            ReviewAction::RetakeShot => State::Capture {
+                captured_textures,
+                captured_images,
+                deadline: countdown,
+                retake: selected,
+            },
+            ReviewAction::Discard => State::Welcome {
+                deadline: now + seconds(context.config.timing.welcome_after_skip),
+            },
+        })
+    }
+
+    /// Leaves the copies screen, printing `copies` of the session.
+    fn choose_copies<T>(
+        self,
+        copies: u32,
+        now: Instant,
+        context: &mut Context<'t, T>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let State::Copies {
+            captured_textures,
+            image_saving_handle,
+            order,
+            ..
+        } = self
+        else {
+            return Ok(self);
+        };
+        order.send(context, copies);
+        Ok(State::Debrief {
+            captured_textures,
+            deadline: now + seconds(context.config.timing.debrief),
+            image_saving_handle,
+        })
+    }
+}
+
+/// Saves a session's photos in the background, asking first how many copies to print if guests
+/// get to choose. `ended` is when the session ended, which the next screen's timeout counts from.
+fn finish_session<'t, T>(
+    context: &mut Context<'t, T>,
+    captured_images: Vec<JoinHandle<RgbImage>>,
+    captured_textures: Vec<Texture<'t>>,
+    ended: Instant,
+) -> Result<State<'t>, Box<dyn std::error::Error>> {
+    let timing = context.config.timing.clone();
+    let post_command = context.config.image.as_ref().and_then(|cfg| {
+        if cfg.enable_post_command {
+            cfg.post_command.as_ref().filter(|v| !v.is_empty()).cloned()
+        } else {
+            None
+        }
+    });
+    let compositor = Arc::clone(&context.compositor);
+    let framing = (context.config.image.as_ref()).map_or(Framing::default(), |cfg| cfg.framing);
+    let orientation = context.config.orientation;
+    let printer = context.config.printer.clone();
+    let print_job = printer.as_ref().map(|_| PrintJob::default());
+    let (copies_sender, copies) = channel();
+    let order = PrintOrder {
+        copies: copies_sender,
+        print_job: print_job.clone(),
+    };
+
+    let prefix = (context.config.image.as_ref())
+        .and_then(|img| img.prefix.as_ref())
+        .map_or("", |s| s.as_str());
+    let format = (context.config.image.as_ref())
+        .and_then(|cfg| cfg.format.as_ref())
+        .map_or("", |s| s.as_str());
+    let format = if format == "PNG" {
+        image::ImageFormat::Png
+    } else {
+        image::ImageFormat::Jpeg
+    };
+    let suffix = if format == image::ImageFormat::Png {
+        "png"
+    } else {
+        "jpeg"
+    };
+    let finished = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let timestamp = finished.format(FILE_TIMESTAMP_FORMAT)?;
+    let text_values = context.text_values(finished)?;
+    context.session_number += 1;
+    let mut saved_path = PathBuf::from(&context.path);
+    saved_path.pop();
+    saved_path.push(format!("{prefix}img_{timestamp}.{suffix}"));
+    eprintln!("{}", saved_path.display());
+    context.last_saved = Some(saved_path.clone());
+    let saved_path = saved_path;
+    let image_saving_handle = std::thread::spawn(move || {
+        let captured_images: Vec<RgbImage> = captured_images
+            .into_iter()
+            .map(|handle| {
+                framing.apply(
+                    &handle.join().unwrap(),
+                    orientation.rotation,
+                    orientation.mirror_saved,
+                )
+            })
+            .collect();
+        let final_image = compositor.compose(&captured_images, &text_values);
+        if let Err(e) = final_image.save_with_format(&saved_path, format) {
+            eprintln!("Saving {} failed: {e}", saved_path.display());
+            return;
+        }
+        // Waits for the guest to choose, if they're being asked.
+        let copies = copies.recv().unwrap_or(0);
+        // Nothing's printed once the guest says no or the quota's used up, eg by `lpr`.
+        if let Some(post_command) = post_command.filter(|_| copies > 0) {
+            let output = Command::new(&post_command[0])
+                .args(&post_command[1..])
+                .arg(&saved_path)
+                .env("ROCKETBOOTH_COPIES", copies.to_string())
+                .output();
+            match output {
+                Ok(output) if !output.status.success() => eprintln!(
+                    "Post command {post_command:?} failed ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Ok(_) => {}
+                Err(e) => eprintln!("Post command {post_command:?} failed to run: {e}"),
+            }
+        }
+        if let (Some(printer), Some(print_job)) = (printer, print_job) {
+            if copies > 0 {
+                // Following the job can take as long as the printer is out of
+                // paper, so it mustn't hold up the end of the session.
+                std::thread::spawn(move || {
+                    print_and_follow(&printer, &saved_path, copies, &print_job)
+                });
+            }
+        }
+    });
+
+    let choices = context.copies_allowed();
+    Ok(
+        match context.config.copies.as_ref().filter(|_| choices > 1) {
+            Some(copies) => {
+                let labels = copy_labels(context, choices)?;
+                State::Copies {
+                    captured_textures,
+                    deadline: ended + seconds(timing.copies_timeout),
+                    image_saving_handle,
+                    order,
+                    choices,
+                    default: copies.default.min(choices),
+                    labels,
+                }
+            }
+            None => {
+                order.send(context, choices);
+                State::Debrief {
+                    captured_textures,
+                    deadline: ended + seconds(timing.debrief),
+                    image_saving_handle,
+                }
+            }
+        },
+    )
+}
+
+/// Carries out a command from the control socket on the booth in `state`, returning how the
+/// booth is doing afterwards, or why the command couldn't be carried out. It's up to the caller
+/// to stop the booth after `shutdown`. Fails if the camera can't be opened for `trigger`.
+pub fn run_command<'t, T>(
+    state: &mut State<'t>,
+    context: &mut Context<'t, T>,
+    command: ControlCommand,
+    now: Instant,
+) -> crate::Result<Reply> {
+    let next = match (command, std::mem::take(state)) {
+        (ControlCommand::Status | ControlCommand::Shutdown, current) => current,
+        (
+            ControlCommand::Trigger,
+            current @ (State::Waiting | State::Welcome { .. } | State::Explainer { .. }),
+        ) => {
+            if let Err(e) = context.camera.acquire() {
+                *state = current;
+                return Err(e);
+            }
+            context.pressed = None;
+            State::Capture {
+                deadline: now + seconds(context.config.timing.countdown),
+                captured_images: vec![],
+                captured_textures: vec![],
+                retake: None,
+            }
+        }
+        (ControlCommand::Pause, current) => {
+            context.paused = true;
+            match current {
+                State::Welcome { .. } => {
+                    context.camera.release();
+                    State::Waiting
+                }
+                current => current,
+            }
+        }
+        (ControlCommand::Resume, current) => {
+            context.paused = false;
+            current
+        }
+        // The session being saved isn't ready to print until it's done.
+        (ControlCommand::Reprint { copies }, current)
+            if !matches!(current, State::Copies { .. } | State::Debrief { .. }) =>
+        {
+            *state = current;
+            return Ok(match reprint(context, copies) {
+                Ok(()) => Reply::done(state.status(context)),
+                Err(e) => Reply::failed(e),
+            });
+        }
+        (ControlCommand::Reload, State::Waiting) => {
+            if let Err(e) = context.reload() {
+                return Ok(Reply::failed(format!("Failed to reload: {e}")));
+            }
+            State::Waiting
+        }
+        (command, current) => {
+            let screen = current.phase().state_name();
+            *state = current;
+            return Ok(Reply::failed(format!(
+                "Can't {} on the {screen} screen",
+                command.name()
+            )));
+        }
+    };
+    *state = next;
+    Ok(Reply::done(state.status(context)))
+}
+
+/// Prints the last session saved again, following the job like any other print. The copies
+/// have to fit within the `[copies]` limits, as a session's prints do, and count towards the
+/// event's quota once the printer takes them.
+fn reprint<T>(context: &mut Context<T>, copies: u32) -> Result<(), String> {
+    let printer = (context.config.printer.clone()).ok_or("No [printer] is set up to print with")?;
+    let path =
+        (context.last_saved.clone()).ok_or("Nothing has been saved since the booth started")?;
+    if copies == 0 {
+        return Err("copies must be at least 1".into());
+    }
+    let allowed = context.copies_allowed();
+    if context.config.copies.is_some() && copies > allowed {
+        return Err(format!(
+            "Can't print {copies} copies; the [copies] limits allow {allowed}"
+        ));
+    }
+    let print_job = PrintJob::default();
+    context.reprints.push((print_job.clone(), copies));
+    context.print_job = Some(print_job.clone());
+    std::thread::spawn(move || print_and_follow(&printer, &path, copies, &print_job));
+    Ok(())
+}
+
+fn review_buttons<T>(context: &Context<T>) -> Vec<Button> {
+    let (_, buttons) = review_layout(context.screen.size());
+    place_buttons(
+        &context.theme.buttons,
+        context.screen.size(),
+        buttons
+            .into_iter()
+            .map(|(action, rect)| (ButtonId::Review(action), rect)),
+    )
+}
+
+fn copies_buttons<T>(context: &Context<T>, choices: u32) -> Vec<Button> {
+    place_buttons(
+        &context.theme.buttons,
+        context.screen.size(),
+        (1..)
+            .zip(copy_buttons(context.screen.size(), choices))
+            .map(|(copies, rect)| (ButtonId::Copies(copies), rect)),
+    )
+}
+
+fn operator_buttons<T>(context: &Context<T>) -> Vec<Button> {
+    let controls = &context.config.controls;
+    let choices = [
+        Some(OperatorChoice::Resume),
+        (controls.enable_backspace_command && !controls.backspace_command.is_empty())
+            .then_some(OperatorChoice::RunCommand),
+        controls.enable_exit_inputs.then_some(OperatorChoice::Quit),
+    ];
+    let choices: Vec<_> = choices.into_iter().flatten().collect();
+    place_buttons(
+        &context.theme.buttons,
+        context.screen.size(),
+        choices
+            .iter()
+            .zip(operator_layout(context.screen.size(), choices.len() as u32))
+            .map(|(&choice, rect)| (ButtonId::Operator(choice), rect)),
+    )
+}
+
+/// The review screen for a session's photos, with the only photo picked already if there's just
+/// one.
+fn review<'t, T>(
+    context: &Context<'t, T>,
+    captured_images: Vec<JoinHandle<RgbImage>>,
+    captured_textures: Vec<Texture<'t>>,
+    now: Instant,
+) -> Result<State<'t>, Box<dyn std::error::Error>> {
+    let prints = context.config.printer.is_some()
+        || (context.config.image.as_ref()).is_some_and(|image| {
+            image.enable_post_command && image.post_command.as_ref().is_some_and(|c| !c.is_empty())
+        });
+    let labels = button_labels(
+        context,
+        &review_buttons(context),
+        0.4,
+        [255, 255, 255],
+        |id| {
+            match id {
+                ButtonId::Review(ReviewAction::Keep) if prints => "Print",
+                ButtonId::Review(ReviewAction::Keep) => "Keep",
+                ButtonId::Review(ReviewAction::RetakeAll) => "Retake all",
+                ButtonId::Review(ReviewAction::RetakeShot) => "Retake photo",
+                ButtonId::Review(ReviewAction::Discard) => "Discard",
+                _ => "",
+            }
+            .into()
+        },
+    )?;
+    Ok(State::Review {
+        selected: (captured_textures.len() == 1).then_some(0),
+        captured_textures,
+        captured_images,
+        deadline: now + seconds(context.config.timing.review_timeout),
+        labels,
+    })
+}
+
+/// Where each of a session's photos goes when they're laid out as in the saved image, as large
+/// as fits within `screen`, along with the area they cover.
+fn photo_rects<T>(
+    context: &Context<T>,
+    captured_textures: &[Texture],
+    screen: Rect,
+) -> (Rect, Vec<(usize, Rect)>) {
+    let layout = (context.config.image.as_ref()).map_or(ImageLayout::default(), |cfg| cfg.layout);
+    let framing = (context.config.image.as_ref()).map_or(Framing::default(), |cfg| cfg.framing);
+    let area = layout_area(
+        screen,
+        layout,
+        framing,
+        context.config.orientation.rotation,
+        captured_textures.first(),
+    );
+    let rects = layout
+        .slots(area.width(), area.height())
+        .iter()
+        .map(|slot| (slot.shot, slot_rect(slot, area)))
+        .collect();
+    (area, rects)
+}
+
+/// Draws a session's photos as they're laid out in the saved image, as large as fits within
+/// `screen`, outlining the `selected` one.
+fn render_photos<T, U>(
+    canvas: &mut Canvas<U>,
+    context: &Context<T>,
+    captured_textures: &[Texture],
+    screen: Rect,
+    selected: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    U: RenderTarget,
+{
+    let layout = (context.config.image.as_ref()).map_or(ImageLayout::default(), |cfg| cfg.layout);
+    let framing = (context.config.image.as_ref()).map_or(Framing::default(), |cfg| cfg.framing);
+    let orientation = context.config.orientation;
+    let (area, rects) = photo_rects(context, captured_textures, screen);
+    clear_to_background(canvas, layout, area)?;
+    for &(shot, rect) in &rects {
+        if let Some(tex) = captured_textures.get(shot) {
+            framing.render(
+                canvas,
+                tex,
+                rect,
+                orientation.rotation,
+                orientation.mirror_review,
+            )?;
+        }
+    }
+    canvas.set_draw_color(Color::RGB(255, 200, 0));
+    for &(_, rect) in rects.iter().filter(|(shot, _)| Some(*shot) == selected) {
+        let thickness = (rect.width().min(rect.height()) / 40).max(3);
+        canvas.fill_rects(&[
+            Rect::new(rect.x(), rect.y(), rect.width(), thickness),
+            Rect::new(
+                rect.x(),
+                rect.bottom() - thickness as i32,
+                rect.width(),
+                thickness,
+            ),
+            Rect::new(rect.x(), rect.y(), thickness, rect.height()),
+            Rect::new(
+                rect.right() - thickness as i32,
+                rect.y(),
+                thickness,
+                rect.height(),
+            ),
+        ])?;
+    }
+    canvas.set_draw_color(Color::BLACK);
+    Ok(())
+}
+
+/// Divides a screen of the given size into the area for the photos on the review screen, and
+/// its buttons in a row along the bottom.
+fn review_layout(screen_size: (u32, u32)) -> (Rect, Vec<(ReviewAction, Rect)>) {
+    let (width, height) = screen_size;
+    let strip = height / 6;
+    let margin = strip / 8;
+    let actions = [
+        ReviewAction::Keep,
+        ReviewAction::RetakeAll,
+        ReviewAction::RetakeShot,
+        ReviewAction::Discard,
+    ];
+    let count = actions.len() as u32;
+    let button_width = (width.saturating_sub(margin * (count + 1)) / count).max(1);
+    let buttons = (0..)
+        .zip(actions)
+        .map(|(i, action)| {
+            let x = margin + i * (button_width + margin);
+            let y = height - strip + margin;
+            let button = Rect::new(
+                x as i32,
+                y as i32,
+                button_width,
+                strip.saturating_sub(2 * margin).max(1),
+            );
+            (action, button)
+        })
+        .collect();
+    (Rect::new(0, 0, width, (height - strip).max(1)), buttons)
+}
+
+/// The operator menu, which closes by itself if it's left open.
+fn operator_menu<'t, T>(
+    context: &Context<'t, T>,
+    now: Instant,
+) -> Result<State<'t>, Box<dyn std::error::Error>> {
+    let labels = button_labels(
+        context,
+        &operator_buttons(context),
+        0.4,
+        [255, 255, 255],
+        |id| {
+            match id {
+                ButtonId::Operator(OperatorChoice::Resume) => "Resume",
+                ButtonId::Operator(OperatorChoice::RunCommand) => "Run command",
+                ButtonId::Operator(OperatorChoice::Quit) => "Quit",
+                _ => "",
+            }
+            .into()
+        },
+    )?;
+    Ok(State::Operator {
+        deadline: now + seconds(context.config.timing.operator_timeout),
+        labels,
+    })
+}
+
+/// The operator menu's buttons, in a column down the middle of a screen of the given size.
+fn operator_layout(screen_size: (u32, u32), count: u32) -> Vec<Rect> {
+    let (width, height) = screen_size;
+    let button_height = (height / 8).max(1);
+    let gap = button_height / 2;
+    let top = height.saturating_sub(count * button_height + count.saturating_sub(1) * gap) / 2;
+    (0..count)
+        .map(|i| {
+            Rect::new(
+                (width / 4) as i32,
+                (top + i * (button_height + gap)) as i32,
+                (width / 2).max(1),
+                button_height,
+            )
+        })
+        .collect()
+}
+
+/// Runs `controls.backspace_command`, if it's enabled.
+fn run_backspace_command<T>(context: &Context<T>) {
+    let controls = &context.config.controls;
+    if controls.enable_backspace_command && !controls.backspace_command.is_empty() {
+        let command = &controls.backspace_command;
+        match Command::new(&command[0]).args(&command[1..]).output() {
+            Ok(output) if !output.status.success() => eprintln!(
+                "Backspace command {command:?} failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("Backspace command {command:?} failed to run: {e}"),
+        }
+    }
+}
+
+/// The colour of each button on the review screen, as red, green and blue.
+fn review_color(action: ReviewAction) -> [u8; 3] {
+    match action {
+        ReviewAction::Keep => [40, 150, 60],
+        ReviewAction::RetakeAll => [220, 140, 20],
+        ReviewAction::RetakeShot => [40, 100, 190],
+        ReviewAction::Discard => [190, 40, 40],
+    }
+}
+
+/// The buttons offering from 1 to `choices` copies, in a row across the middle of a screen of
+/// the given size.
+fn copy_buttons(screen_size: (u32, u32), choices: u32) -> Vec<Rect> {
+    let (width, height) = screen_size;
+    // Each button is separated from the next by half its width.
+    let size = (width as f64 / (choices as f64 * 1.5 + 0.5))
+        .min(height as f64 / 4.0)
+        .max(1.0);
+    let gap = size / 2.0;
+    let left = (width as f64 - choices as f64 * size - (choices - 1) as f64 * gap) / 2.0;
+    let top = ((height as f64 - size) / 2.0).round() as i32;
+    (0..choices)
+        .map(|i| {
+            let x = (left + i as f64 * (size + gap)).round() as i32;
+            Rect::new(x, top, size as u32, size as u32)
+        })
+        .collect()
+}
+
+/// The numbers for the copies buttons, written in the theme's font if it has one.
+fn copy_labels<'t, T>(
+    context: &Context<'t, T>,
+    choices: u32,
+) -> Result<Vec<Texture<'t>>, Box<dyn std::error::Error>> {
+    button_labels(
+        context,
+        &copies_buttons(context, choices),
+        0.6,
+        [0, 0, 0],
+        |id| match id {
+            ButtonId::Copies(copies) => copies.to_string(),
+            _ => String::new(),
+        },
+    )
+}
+
+/// The writing for each of `buttons`, centred and `size` times as tall as the button, if the
+/// theme has a font to write it in.
+fn button_labels<'t, T>(
+    context: &Context<'t, T>,
+    buttons: &[Button],
+    size: f32,
+    color: [u8; 3],
+    text: impl Fn(ButtonId) -> String,
+) -> Result<Vec<Texture<'t>>, Box<dyn std::error::Error>> {
+    let Some(font) = &context.font else {
+        return Ok(vec![]);
+    };
+    buttons
+        .iter()
+        .map(|button| {
+            let (width, height) = button.rect.size();
+            let mut image = RgbaImage::new(width, height);
+            let field = TextField {
+                text: text(button.id),
+                x: width as i32 / 2,
+                y: (height as f32 * (1.0 - size) / 2.0) as i32,
+                size: height as f32 * size,
+                color,
+                align: Align::Center,
+                font: None,
+            };
+            draw_text(&mut image, &field, font, &field.text);
+            image_to_texture(image, context.texture_creator)
+        })
+        .collect()
+}
+
+/// Dots counting out `copies` on a button, for themes without a font.
+fn pips(button: Rect, copies: u32) -> Vec<Rect> {
+    let columns = (copies as f64).sqrt().ceil() as u32;
+    let rows = copies.div_ceil(columns);
+    let cell = button.width() / (columns.max(rows) + 1);
+    let pip = (cell / 2).max(1);
+    let (left, top) = (
+        button.center().x() - (columns * cell) as i32 / 2,
+        button.center().y() - (rows * cell) as i32 / 2,
+    );
+    (0..copies)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            Rect::from_center(
+                Point::new(
+                    left + (column * cell + cell / 2) as i32,
+                    top + (row * cell + cell / 2) as i32,
+                ),
+                pip,
+                pip,
+            )
+        })
+        .collect()
+}
+
+/// The number on a key, for choosing copies from a keyboard.
+fn digit(keycode: Keycode) -> Option<u32> {
+    char::from_u32(keycode.into_i32() as u32)?.to_digit(10)
+}
+
+/// The part of `screen` showing a session's photos: the shape of the saved image, as large as
+/// fits. `frame` is any of the session's frames, or the whole screen is used if there are none
+/// yet.
+fn layout_area(
+    screen: Rect,
+    layout: ImageLayout,
+    framing: Framing,
+    rotation: u32,
+    frame: Option<&Texture>,
+) -> Rect {
+    let Some(frame) = frame else {
+        return screen;
+    };
+    let query = frame.query();
+    let (width, height) = rotated_size(query.width, query.height, rotation);
+    let (photo_width, photo_height) = framing.framed_size(width, height);
+    let (width, height) = layout.dest_size(photo_width, photo_height);
+    place(width, height, Scale::Fit, Anchor::Center, screen)
+}
+
+/// The problems holding up the latest print job, if any.
+fn printer_problems<T>(context: &Context<T>) -> Vec<PrinterProblem> {
+    context
+        .print_job
+        .as_ref()
+        .map_or(vec![], |print_job| print_job.problems())
+}
+
+/// The screen showing `problems`, written out in the theme's font if it has one.
+fn printer_error<'t, T>(
+    context: &Context<'t, T>,
+    problems: Vec<PrinterProblem>,
+) -> Result<State<'t>, Box<dyn std::error::Error>> {
+    let message = match &context.font {
+        Some(font) => {
+            let (width, height) = context.screen.size();
+            let size = (height as f32 / 16.0).max(16.0);
+            let line_height = (size * 1.5).ceil() as u32;
+            let mut image = RgbaImage::new(width, line_height * problems.len() as u32);
+            for (line, problem) in (0..).zip(&problems) {
+                let field = TextField {
+                    text: problem.to_string(),
+                    x: width as i32 / 2,
+                    y: (line * line_height) as i32,
+                    size,
+                    color: [255, 255, 255],
+                    align: Align::Center,
+                    font: None,
+                };
+                draw_text(&mut image, &field, font, &field.text);
+            }
+            Some(image_to_texture(image, context.texture_creator)?)
+        }
+        None => None,
+    };
+    Ok(State::PrinterError { problems, message })
+}
+
+fn slot_rect(slot: &Slot, area: Rect) -> Rect {
+    Rect::new(
+        area.x() + slot.x as i32,
+        area.y() + slot.y as i32,
+        slot.width,
+        slot.height,
+    )
+}
+
+/// Clears the canvas to black, with the colour behind the layout's photos over `area`, leaving
+/// the draw colour black.
+fn clear_to_background<U>(
+    canvas: &mut Canvas<U>,
+    layout: ImageLayout,
+    area: Rect,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    U: RenderTarget,
+{
+    let [r, g, b] = layout.background();
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.fill_rect(area)?;
+    canvas.set_draw_color(Color::BLACK);
+    Ok(())
+}
+
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or_default()
+}
+
+/// Picks which of `steps` countdown images to show with `remaining` time left of a countdown
+/// lasting `countdown`, spreading them evenly over its length. `None` before the countdown has
+/// started, ie during the gap between shots.
+fn countdown_step(remaining: Duration, countdown: Duration, steps: usize) -> Option<usize> {
+    if steps == 0 || countdown.is_zero() || remaining > countdown {
+        return None;
+    }
+    let elapsed = (countdown - remaining).as_secs_f64() / countdown.as_secs_f64();
+    Some(((elapsed * steps as f64) as usize).min(steps - 1))
+}
+
+/// Draws the "camera reconnecting" card, or a plain placeholder if the prompts don't include one.
+fn render_reconnecting<T, U>(
+    canvas: &mut Canvas<U>,
+    context: &Context<T>,
+    rect: Option<Rect>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    U: RenderTarget,
+{
+    match &context.theme.error {
+        Some(card) => card.render(canvas, rect)?,
+        None => {
+            canvas.set_draw_color(Color::RGB(48, 48, 48));
+            canvas.fill_rect(rect)?;
+            canvas.set_draw_color(Color::BLACK);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sdl2::{
+        pixels::PixelFormatEnum,
+        surface::{Surface, SurfaceContext},
+    };
+
+    use super::*;
+    use crate::print::PrintStatus;
+
+    /// Runs `test` on a booth set up from `config` in a directory of its own, themed with the
+    /// repository's prompts and drawn on a software renderer, so it needs no display.
+    fn with_booth(
+        name: &str,
+        config: &str,
+        test: impl for<'t> FnOnce(&mut State<'t>, &mut Context<'t, SurfaceContext<'static>>),
+    ) {
+        let dir =
+            std::env::temp_dir().join(format!("rocketbooth-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let prompts = Path::new(env!("CARGO_MANIFEST_DIR")).join("../prompts");
+        let config_path = dir.join("Rocketbooth.toml");
+        let config = format!(
+            "theme = {:?}\n[video_source]\npath = \"/dev/rocketbooth-missing\"\n{config}",
+            prompts.display().to_string()
+        );
+        std::fs::write(&config_path, config).unwrap();
+        let canvas = Surface::new(320, 240, PixelFormatEnum::RGBA32)
+            .unwrap()
+            .into_canvas()
+            .unwrap();
+        let texture_creator = canvas.texture_creator();
+        let mut context = ContextBuilder::from_file(&config_path)
+            .unwrap()
+            .build(&texture_creator, (320, 240))
+            .unwrap();
+        let mut state = State::default();
+        test(&mut state, &mut context);
+        drop(context);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn run<'t, T>(
+        state: &mut State<'t>,
+        context: &mut Context<'t, T>,
+        command: ControlCommand,
+    ) -> Reply {
+        run_command(state, context, command, Instant::now()).unwrap()
+    }
+
+    fn error(reply: Reply) -> String {
+        assert!(!reply.ok);
+        reply.error.unwrap()
+    }
+
+    #[test]
+    fn status_reports_the_booth_without_changing_it() {
+        with_booth("status", "", |state, context| {
+            let status = run(state, context, ControlCommand::Status).status.unwrap();
+            assert_eq!(status.state, "idle");
+            assert_eq!(status.shot, None);
+            assert!(!status.paused);
+            assert_eq!(status.session_number, 1);
+            assert!(matches!(state, State::Waiting));
+        });
+    }
+
+    #[test]
+    fn trigger_starts_the_countdown_from_the_idle_screens() {
+        with_booth("trigger", "", |state, context| {
+            let status = run(state, context, ControlCommand::Trigger).status.unwrap();
+            assert_eq!((status.state.as_str(), status.shot), ("capture", Some(1)));
+            assert!(matches!(state, State::Capture { .. }));
+            let reply = run(state, context, ControlCommand::Trigger);
+            assert_eq!(error(reply), "Can't trigger on the capture screen");
+            assert!(matches!(state, State::Capture { .. }));
+        });
+    }
+
+    #[test]
+    fn pause_holds_the_booth_on_the_idle_screen_until_resumed() {
+        with_booth("pause", "", |state, context| {
+            *state = State::Welcome {
+                deadline: Instant::now(),
+            };
+            let status = run(state, context, ControlCommand::Pause).status.unwrap();
+            assert!(status.paused && context.paused);
+            assert_eq!(status.state, "idle");
+            assert!(matches!(state, State::Waiting));
+            let status = run(state, context, ControlCommand::Resume).status.unwrap();
+            assert!(!status.paused && !context.paused);
+        });
+    }
+
+    #[test]
+    fn reload_reads_the_config_again_while_idle() {
+        with_booth("reload", "", |state, context| {
+            let config = std::fs::read_to_string(&context.path).unwrap();
+            let renamed = config.clone() + "[event]\nname = \"Reloaded\"\n";
+            std::fs::write(&context.path, renamed).unwrap();
+            context.paused = true;
+            assert!(run(state, context, ControlCommand::Reload).ok);
+            assert_eq!(context.config.event.name, "Reloaded");
+            assert!(context.paused);
+
+            std::fs::write(&context.path, "not a config").unwrap();
+            let reply = run(state, context, ControlCommand::Reload);
+            assert!(error(reply).starts_with("Failed to reload"));
+            assert_eq!(context.config.event.name, "Reloaded");
+
+            run(state, context, ControlCommand::Trigger);
+            let reply = run(state, context, ControlCommand::Reload);
+            assert_eq!(error(reply), "Can't reload on the capture screen");
+        });
+    }
+
+    #[test]
+    fn reprint_needs_a_printer_and_a_saved_session() {
+        with_booth("reprint", "", |state, context| {
+            let reply = run(state, context, ControlCommand::Reprint { copies: 1 });
+            assert_eq!(error(reply), "No [printer] is set up to print with");
+            context.config.printer = Some(toml::from_str("").unwrap());
+            let reply = run(state, context, ControlCommand::Reprint { copies: 1 });
+            assert_eq!(
+                error(reply),
+                "Nothing has been saved since the booth started"
+            );
+            context.last_saved = Some("img_1.jpg".into());
+            let reply = run(state, context, ControlCommand::Reprint { copies: 0 });
+            assert_eq!(error(reply), "copies must be at least 1");
+            assert!(context.print_job.is_none());
+            assert!(run(state, context, ControlCommand::Reprint { copies: 2 }).ok);
+            assert!(context.print_job.is_some());
+        });
+    }
+
+    #[test]
+    fn reprints_count_towards_the_quota_once_the_printer_takes_them() {
+        let copies =
+            "[image]\npost_command = [\"true\"]\n[copies]\nmax_per_session = 3\nevent_quota = 4\n";
+        with_booth("reprint-quota", copies, |state, context| {
+            context.config.printer = Some(toml::from_str("").unwrap());
+            context.last_saved = Some("img_1.jpg".into());
+            let reply = run(state, context, ControlCommand::Reprint { copies: 4 });
+            assert_eq!(
+                error(reply),
+                "Can't print 4 copies; the [copies] limits allow 3"
+            );
+
+            // There's no printer to take this one, so it's never counted.
+            assert!(run(state, context, ControlCommand::Reprint { copies: 2 }).ok);
+            assert_eq!(context.prints_made, 0);
+            assert_eq!(context.copies_allowed(), 2);
+            let print_job = context.print_job.clone().unwrap();
+            while !matches!(print_job.status(), PrintStatus::Failed(_)) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            context.count_reprints();
+            assert_eq!((context.prints_made, context.copies_allowed()), (0, 3));
+
+            let taken = PrintJob::default();
+            taken.accept();
+            context.reprints.push((taken, 3));
+            let reply = run(state, context, ControlCommand::Reprint { copies: 2 });
+            assert_eq!(
+                error(reply),
+                "Can't print 2 copies; the [copies] limits allow 1"
+            );
+            context.count_reprints();
+            assert!(context.reprints.is_empty());
+            assert_eq!((context.prints_made, context.copies_allowed()), (3, 1));
+            let count = context.path.with_file_name("prints_made.txt");
+            assert_eq!(std::fs::read_to_string(count).unwrap(), "3\n");
+        });
+    }
+
+    #[test]
+    fn shutdown_is_answered_with_the_booth_as_it_is() {
+        with_booth("shutdown", "", |state, context| {
+            let status = run(state, context, ControlCommand::Shutdown)
+                .status
+                .unwrap();
+            assert_eq!(status.state, "idle");
+        });
+    }
+}
@@ -0,0 +1,245 @@
+use std::{collections::HashMap, path::Path};
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{Pixel, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// A line of text drawn on the saved image, eg the event's name and date.
+///
+/// Names in braces, like `{event_name}`, are filled in when each image is saved. `{{` and `}}`
+/// stand for literal braces.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TextField {
+    pub text: String,
+    /// Horizontal position in pixels; which part of the text lines up with it depends on
+    /// `align`.
+    pub x: i32,
+    /// Position of the top of the text in pixels.
+    pub y: i32,
+    /// Height of the text in pixels.
+    #[serde(default = "default_text_size")]
+    pub size: f32,
+    /// As red, green and blue.
+    #[serde(default)]
+    pub color: [u8; 3],
+    #[serde(default)]
+    pub align: Align,
+    /// A TTF or OTF font for this text, relative to the template file. Without one, the
+    /// template's font is used.
+    pub font: Option<String>,
+}
+
+/// Which part of a line of text is placed at its `x` position.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+fn default_text_size() -> f32 {
+    48.0
+}
+
+impl TextField {
+    /// Checks the field's settings, and that it only names values in `names`.
+    pub fn validate(&self, names: &[&str]) -> Result<(), String> {
+        if !(self.size.is_finite() && self.size > 0.0) {
+            return Err(format!(
+                "Text size must be a number of pixels, not {}",
+                self.size
+            ));
+        }
+        for name in placeholders(&self.text)? {
+            if !names.contains(&name) {
+                return Err(format!(
+                    "Unknown name {{{name}}} in text \"{}\"; expected one of {}",
+                    self.text,
+                    names
+                        .iter()
+                        .map(|name| format!("{{{name}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn load_font(path: &Path) -> crate::Result<FontArc> {
+    let data =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    Ok(FontArc::try_from_vec(data)
+        .map_err(|e| format!("Failed to load font {}: {e}", path.display()))?)
+}
+
+/// The names in braces in `text`.
+fn placeholders(text: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with("{{") || rest[start..].starts_with("}}") {
+            rest = &rest[start + 2..];
+            continue;
+        }
+        if rest[start..].starts_with('}') {
+            return Err(format!("Unmatched }} in text \"{text}\""));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unmatched {{ in text \"{text}\""))?;
+        names.push(&rest[start + 1..start + end]);
+        rest = &rest[start + end + 1..];
+    }
+    Ok(names)
+}
+
+/// Replaces the names in braces in `text` with their values. Unknown names are left as they
+/// are; `TextField::validate` catches them when the template is loaded.
+pub fn fill_in(text: &str, values: &HashMap<String, String>) -> String {
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '}']) {
+        filled.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            filled.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        match (tail.starts_with('{'), tail.find('}')) {
+            (true, Some(end)) => {
+                let name = &tail[1..end];
+                match values.get(name) {
+                    Some(value) => filled.push_str(value),
+                    None => filled.push_str(&tail[..=end]),
+                }
+                rest = &tail[end + 1..];
+            }
+            _ => {
+                filled.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Draws a single line of `text` onto the canvas, placed and coloured as `field` says.
+pub fn draw_text(canvas: &mut RgbaImage, field: &TextField, font: &FontArc, text: &str) {
+    let font = font.as_scaled(PxScale::from(field.size));
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += font.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(font.scale(), point(caret, font.ascent())));
+        caret += font.h_advance(id);
+        previous = Some(id);
+    }
+
+    let left = match field.align {
+        Align::Left => field.x as f32,
+        Align::Center => field.x as f32 - caret / 2.0,
+        Align::Right => field.x as f32 - caret,
+    };
+    let [r, g, b] = field.color;
+    for mut glyph in glyphs {
+        glyph.position.x += left;
+        glyph.position.y += field.y as f32;
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let x = bounds.min.x as i64 + x as i64;
+            let y = bounds.min.y as i64 + y as i64;
+            if x < 0 || y < 0 || x >= canvas.width() as i64 || y >= canvas.height() as i64 {
+                return;
+            }
+            let alpha = (255.0 * coverage.clamp(0.0, 1.0)).round() as u8;
+            canvas
+                .get_pixel_mut(x as u32, y as u32)
+                .blend(&Rgba([r, g, b, alpha]));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> HashMap<String, String> {
+        HashMap::from([
+            ("event_name".to_string(), "Ada's party".to_string()),
+            ("date".to_string(), "2024-06-01".to_string()),
+        ])
+    }
+
+    #[test]
+    fn placeholders_are_the_names_in_braces() {
+        assert_eq!(
+            placeholders("{event_name}, {date}").unwrap(),
+            ["event_name", "date"]
+        );
+        assert_eq!(
+            placeholders("{event_name}{date}").unwrap(),
+            ["event_name", "date"]
+        );
+        assert_eq!(placeholders("{{event_name}} {{").unwrap(), [] as [&str; 0]);
+        assert_eq!(placeholders("{{{date}}}").unwrap(), ["date"]);
+    }
+
+    #[test]
+    fn unmatched_braces_are_errors() {
+        assert!(placeholders("Hi {event_name")
+            .unwrap_err()
+            .contains("Unmatched {"));
+        assert!(placeholders("Hi event_name}")
+            .unwrap_err()
+            .contains("Unmatched }"));
+    }
+
+    #[test]
+    fn unknown_placeholders_fail_validation() {
+        let field: TextField = toml::from_str("text = \"{guest}\"\nx = 0\ny = 0").unwrap();
+        let error = field.validate(&["event_name", "date"]).unwrap_err();
+        assert!(error.contains("Unknown name {guest}"), "{error}");
+        assert!(field.validate(&["guest"]).is_ok());
+    }
+
+    #[test]
+    fn fill_in_replaces_the_names_it_knows() {
+        let values = values();
+        assert_eq!(
+            fill_in("{event_name}, {date}", &values),
+            "Ada's party, 2024-06-01"
+        );
+        assert_eq!(
+            fill_in("{event_name}{date}", &values),
+            "Ada's party2024-06-01"
+        );
+        assert_eq!(fill_in("Hi {guest}!", &values), "Hi {guest}!");
+    }
+
+    #[test]
+    fn fill_in_unescapes_doubled_braces() {
+        let values = values();
+        assert_eq!(fill_in("{{event_name}}", &values), "{event_name}");
+        assert_eq!(fill_in("{{{date}}}", &values), "{2024-06-01}");
+    }
+
+    #[test]
+    fn fill_in_keeps_unmatched_braces() {
+        let values = values();
+        assert_eq!(fill_in("Hi {event_name", &values), "Hi {event_name");
+        assert_eq!(fill_in("} {date} {", &values), "} 2024-06-01 {");
+    }
+}
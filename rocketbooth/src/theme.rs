@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use sdl2::{
+    rect::Rect,
+    render::{Canvas, RenderTarget, Texture},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    widget::{ButtonId, ButtonStyle},
+    Config,
+};
+
+/// The images shown on each of the booth's screens, by role.
+///
+/// A theme is a directory holding a `theme.toml` manifest along with its images. Asset paths in
+/// the manifest are relative to that directory. `T` is the asset itself, eg a path while the
+/// manifest is being read and a texture once the theme is loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Theme<T> {
+    /// Shown while the booth is idle. Without one, the screen is left black.
+    pub idle: Option<Themed<T>>,
+    /// The title card shown when a guest wakes the booth up.
+    pub welcome: Themed<T>,
+    /// Instructions overlaid on the camera preview before the photos are taken.
+    pub explainer: Themed<T>,
+    /// Overlaid on the preview during the countdown before each photo, in order, spread evenly
+    /// over its length.
+    pub countdown: Vec<Themed<T>>,
+    /// Overlaid on the photos while they're being saved and printed.
+    pub processing: Themed<T>,
+    /// Shown in place of the camera preview while the camera is being reconnected.
+    pub error: Option<Themed<T>>,
+    /// Overlaid on the photos on the review screen, above its buttons, eg with instructions.
+    pub review: Option<Themed<T>>,
+    /// Overlaid on the photos behind the buttons for choosing how many prints to make, eg
+    /// asking "How many prints?".
+    pub copies: Option<Themed<T>>,
+    /// Overlaid on the photos once they've been saved. Without one, `processing` stays up.
+    pub thank_you: Option<Themed<T>>,
+    /// Shown when the printer needs attention, eg it's out of paper, with the problem written
+    /// over it if the theme has a font. Without one, `error` is used.
+    pub printer_error: Option<Themed<T>>,
+    /// How the buttons on the review and copies screens look and where they go, by name, eg
+    /// `keep` or `copies_2`. Buttons left out are drawn plainly in the screen's own layout.
+    #[serde(default = "HashMap::new")]
+    pub buttons: HashMap<String, ButtonStyle<T>>,
+    /// A TTF or OTF font for text on the saved images and the screens' buttons, for print
+    /// templates that don't give their own.
+    pub font: Option<PathBuf>,
+}
+
+/// An asset along with how it should be placed on screen.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Themed<T> {
+    pub image: T,
+    #[serde(default)]
+    pub scale: Scale,
+    #[serde(default)]
+    pub anchor: Anchor,
+}
+
+/// How an image is sized to the area it's drawn in.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale {
+    /// Stretch to cover the area exactly, ignoring the image's aspect ratio.
+    #[default]
+    Stretch,
+    /// As large as possible while staying inside the area.
+    Fit,
+    /// As small as possible while covering the area; the excess is cropped.
+    Fill,
+    /// The image's own size in pixels.
+    Original,
+}
+
+/// Where an image that doesn't cover its area is placed within it.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// The position along each axis, from 0 (left/top) to 2 (right/bottom).
+    fn position(self) -> (i32, i32) {
+        match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        }
+    }
+}
+
+impl<T> Theme<T> {
+    /// Converts every asset in the theme, stopping at the first failure.
+    pub fn try_map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<Theme<U>, E> {
+        let mut buttons = HashMap::new();
+        for (name, style) in self.buttons {
+            let style = ButtonStyle {
+                area: style.area,
+                image: style.image.map(&mut f).transpose()?,
+                pressed: style.pressed.map(&mut f).transpose()?,
+            };
+            buttons.insert(name, style);
+        }
+        let mut themed = |asset: Themed<T>| -> Result<Themed<U>, E> {
+            Ok(Themed {
+                image: f(asset.image)?,
+                scale: asset.scale,
+                anchor: asset.anchor,
+            })
+        };
+        Ok(Theme {
+            idle: self.idle.map(&mut themed).transpose()?,
+            welcome: themed(self.welcome)?,
+            explainer: themed(self.explainer)?,
+            countdown: self
+                .countdown
+                .into_iter()
+                .map(&mut themed)
+                .collect::<Result<_, _>>()?,
+            processing: themed(self.processing)?,
+            error: self.error.map(&mut themed).transpose()?,
+            review: self.review.map(&mut themed).transpose()?,
+            copies: self.copies.map(&mut themed).transpose()?,
+            thank_you: self.thank_you.map(&mut themed).transpose()?,
+            printer_error: self.printer_error.map(&mut themed).transpose()?,
+            buttons,
+            font: self.font,
+        })
+    }
+}
+
+impl Theme<PathBuf> {
+    /// Reads the manifest in `theme_dir`, resolving the asset paths it lists.
+    pub fn from_dir(theme_dir: &Path) -> crate::Result<Self> {
+        let manifest_path = theme_dir.join("theme.toml");
+        let manifest = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("Failed to read {}: {e}", manifest_path.display()))?;
+        let theme: Self = toml::from_str(&manifest)
+            .map_err(|e| format!("Invalid theme {}: {e}", manifest_path.display()))?;
+        if theme.countdown.is_empty() {
+            return Err(format!(
+                "{} must list at least one countdown image",
+                manifest_path.display()
+            )
+            .into());
+        }
+        for (name, style) in &theme.buttons {
+            if ButtonId::from_name(name).is_none() {
+                let names: Vec<_> = ButtonId::NAMED.iter().map(ButtonId::name).collect();
+                return Err(format!(
+                    "Unknown button {name} in {}; expected {} or copies_1, copies_2 and so on",
+                    manifest_path.display(),
+                    names.join(", ")
+                )
+                .into());
+            }
+            if let Some(area) = &style.area {
+                area.validate().map_err(|e| {
+                    format!(
+                        "Invalid area for button {name} in {}: {e}",
+                        manifest_path.display()
+                    )
+                })?;
+            }
+        }
+        let mut theme =
+            theme.try_map(|path| -> crate::Result<PathBuf> { Ok(theme_dir.join(path)) })?;
+        theme.font = theme.font.map(|font| theme_dir.join(font));
+        Ok(theme)
+    }
+
+    /// The theme for a config without one: the fixed prompt files in the `prompts` directory next
+    /// to the config file.
+    pub fn from_prompts(config_dir: &Path, config: &Config) -> crate::Result<Self> {
+        let themed = |path: PathBuf| Themed {
+            image: config_dir.join(path),
+            scale: Scale::default(),
+            anchor: Anchor::default(),
+        };
+        let reconnecting = PathBuf::from("prompts/reconnecting.png");
+        let review = PathBuf::from("prompts/review.png");
+        let copies = PathBuf::from("prompts/copies.png");
+        let printer_error = PathBuf::from("prompts/printer_error.png");
+        Ok(Self {
+            idle: None,
+            welcome: themed("prompts/prompts.001.png".into()),
+            explainer: themed("prompts/prompts.002.png".into()),
+            countdown: countdown_paths(config_dir, config)?
+                .into_iter()
+                .map(themed)
+                .collect(),
+            processing: themed("prompts/prompts.007.png".into()),
+            error: config_dir
+                .join(&reconnecting)
+                .exists()
+                .then(|| themed(reconnecting)),
+            review: config_dir.join(&review).exists().then(|| themed(review)),
+            copies: config_dir.join(&copies).exists().then(|| themed(copies)),
+            thank_you: None,
+            printer_error: config_dir
+                .join(&printer_error)
+                .exists()
+                .then(|| themed(printer_error)),
+            buttons: HashMap::new(),
+            font: None,
+        })
+    }
+}
+
+impl<'t> Themed<Texture<'t>> {
+    /// Draws the image within `area`, or the canvas's whole viewport if there is none.
+    pub fn render<U>(&self, canvas: &mut Canvas<U>, area: Option<Rect>) -> crate::Result<()>
+    where
+        U: RenderTarget,
+    {
+        let area = area.unwrap_or_else(|| canvas.viewport());
+        let query = self.image.query();
+        let dest = place(query.width, query.height, self.scale, self.anchor, area);
+        // Filled images may spill out of their area, so keep them inside it.
+        let clip = canvas.clip_rect();
+        canvas.set_clip_rect(area);
+        let result = canvas.copy(&self.image, None, dest);
+        canvas.set_clip_rect(clip);
+        Ok(result?)
+    }
+}
+
+/// The rectangle an image of the given size covers when drawn within `area`.
+pub(crate) fn place(width: u32, height: u32, scale: Scale, anchor: Anchor, area: Rect) -> Rect {
+    let (area_width, area_height) = (area.width() as f64, area.height() as f64);
+    let factor = match scale {
+        Scale::Stretch => return area,
+        Scale::Fit => f64::min(area_width / width as f64, area_height / height as f64),
+        Scale::Fill => f64::max(area_width / width as f64, area_height / height as f64),
+        Scale::Original => 1.0,
+    };
+    let dest_width = (width as f64 * factor).round() as u32;
+    let dest_height = (height as f64 * factor).round() as u32;
+    let (x_position, y_position) = anchor.position();
+    let x = area.x() + (area.width() as i32 - dest_width as i32) * x_position / 2;
+    let y = area.y() + (area.height() as i32 - dest_height as i32) * y_position / 2;
+    Rect::new(x, y, dest_width.max(1), dest_height.max(1))
+}
+
+/// The images for the countdown, relative to the config file's directory: the list from the
+/// config if there is one, otherwise the numbered files in `prompts/countdown`, otherwise the
+/// original four countdown prompts.
+fn countdown_paths(config_dir: &Path, config: &Config) -> crate::Result<Vec<PathBuf>> {
+    if let Some(countdown) = &config.prompts.countdown {
+        if countdown.is_empty() {
+            return Err("prompts.countdown must list at least one image".into());
+        }
+        return Ok(countdown.iter().map(PathBuf::from).collect());
+    }
+
+    let countdown_dir = Path::new("prompts/countdown");
+    if !config_dir.join(countdown_dir).is_dir() {
+        return Ok((3..=6)
+            .map(|i| PathBuf::from(format!("prompts/prompts.{i:03}.png")))
+            .collect());
+    }
+
+    // Files are named for their position in the countdown, eg 1.png, 2.png, 3.png.
+    let mut numbered = Vec::new();
+    for entry in config_dir.join(countdown_dir).read_dir()? {
+        let file_name = entry?.file_name();
+        if file_name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = countdown_dir.join(file_name);
+        let number = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<usize>().ok())
+            .ok_or_else(|| {
+                format!(
+                    "Unexpected file {} in the countdown directory; images should be named 1.png, 2.png, ...",
+                    path.display()
+                )
+            })?;
+        numbered.push((number, path));
+    }
+    numbered.sort();
+    if numbered.is_empty() {
+        return Err(format!("No countdown images in {}", countdown_dir.display()).into());
+    }
+    for (expected, (number, path)) in (1..).zip(&numbered) {
+        if *number != expected {
+            return Err(format!(
+                "Countdown images should be numbered 1 to {} without gaps or repeats, but found {} where {expected} was expected",
+                numbered.len(),
+                path.display()
+            )
+            .into());
+        }
+    }
+    Ok(numbered.into_iter().map(|(_, path)| path).collect())
+}
@@ -0,0 +1,311 @@
+use std::time::{Duration, Instant};
+
+use sdl2::{event::Event, rect::Point};
+
+use crate::{config::TouchSettings, display::Screen};
+
+/// The mouse SDL reports for the mouse events it makes up from touches, which are ignored in
+/// favour of the touches themselves.
+pub const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+/// What a finger on the screen did.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// A finger touched the screen here.
+    Press(Point),
+    /// The finger was lifted here, without swiping.
+    Release(Point),
+    /// The finger was dragged across the screen and lifted.
+    Swipe(SwipeDirection),
+    /// The finger was held still on the screen.
+    LongPress(Point),
+}
+
+/// Which way a finger was dragged, on the screen the booth draws.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+struct Finger {
+    id: i64,
+    start: Point,
+    since: Instant,
+    /// Whether it has moved too far to be a long press.
+    moved: bool,
+    long_pressed: bool,
+}
+
+/// Follows one finger at a time on the touch screen, turning its events into gestures. Other
+/// fingers touching at the same time are ignored, so a hand resting on the screen doesn't press
+/// anything.
+#[derive(Debug, Default)]
+pub struct TouchTracker {
+    finger: Option<Finger>,
+    last_press: Option<Instant>,
+}
+
+impl TouchTracker {
+    /// The gesture a finger event completes, if any.
+    pub fn track(
+        &mut self,
+        event: &Event,
+        now: Instant,
+        settings: &TouchSettings,
+        screen: &Screen,
+    ) -> Option<Gesture> {
+        match *event {
+            Event::FingerDown {
+                finger_id, x, y, ..
+            } => {
+                let bouncing = self
+                    .last_press
+                    .is_some_and(|last| now < last + seconds(settings.debounce));
+                if self.finger.is_some() || bouncing {
+                    return None;
+                }
+                let point = screen.touch_to_screen(x, y);
+                self.finger = Some(Finger {
+                    id: finger_id,
+                    start: point,
+                    since: now,
+                    moved: false,
+                    long_pressed: false,
+                });
+                self.last_press = Some(now);
+                Some(Gesture::Press(point))
+            }
+            Event::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                let finger = self
+                    .finger
+                    .as_mut()
+                    .filter(|finger| finger.id == finger_id)?;
+                let (dx, dy) = offset(finger.start, screen.touch_to_screen(x, y));
+                // Fingers wobble, so a long press can drift by a little.
+                let slop = settings.swipe_distance * screen.size().0 as f64 / 4.0;
+                finger.moved |= dx.abs().max(dy.abs()) as f64 > slop;
+                None
+            }
+            Event::FingerUp {
+                finger_id, x, y, ..
+            } => {
+                let finger = self.finger.take_if(|finger| finger.id == finger_id)?;
+                if finger.long_pressed {
+                    return None;
+                }
+                let point = screen.touch_to_screen(x, y);
+                let (dx, dy) = offset(finger.start, point);
+                let swipe_distance = settings.swipe_distance * screen.size().0 as f64;
+                if settings.gestures && dx.abs() as f64 >= swipe_distance && dx.abs() > dy.abs() {
+                    Some(Gesture::Swipe(if dx < 0 {
+                        SwipeDirection::Left
+                    } else {
+                        SwipeDirection::Right
+                    }))
+                } else {
+                    Some(Gesture::Release(point))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// A long press, once a finger has been held still for long enough. Each touch makes at
+    /// most one, and isn't released afterwards.
+    pub fn long_press(&mut self, now: Instant, settings: &TouchSettings) -> Option<Gesture> {
+        if !settings.gestures {
+            return None;
+        }
+        let finger = self.finger.as_mut().filter(|finger| {
+            !finger.moved
+                && !finger.long_pressed
+                && now >= finger.since + seconds(settings.long_press)
+        })?;
+        finger.long_pressed = true;
+        Some(Gesture::LongPress(finger.start))
+    }
+}
+
+fn offset(from: Point, to: Point) -> (i32, i32) {
+    (to.x() - from.x(), to.y() - from.y())
+}
+
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A landscape screen, 800 pixels wide, so a swipe has to cover 160 of them by default.
+    fn screen() -> Screen {
+        Screen::new((800, 480), 0)
+    }
+
+    fn gestures() -> TouchSettings {
+        TouchSettings {
+            gestures: true,
+            ..TouchSettings::default()
+        }
+    }
+
+    fn down(x: f32, y: f32) -> Event {
+        Event::FingerDown {
+            timestamp: 0,
+            touch_id: 0,
+            finger_id: 1,
+            x,
+            y,
+            dx: 0.0,
+            dy: 0.0,
+            pressure: 1.0,
+        }
+    }
+
+    fn moved(x: f32, y: f32) -> Event {
+        Event::FingerMotion {
+            timestamp: 0,
+            touch_id: 0,
+            finger_id: 1,
+            x,
+            y,
+            dx: 0.0,
+            dy: 0.0,
+            pressure: 1.0,
+        }
+    }
+
+    fn up(x: f32, y: f32) -> Event {
+        Event::FingerUp {
+            timestamp: 0,
+            touch_id: 0,
+            finger_id: 1,
+            x,
+            y,
+            dx: 0.0,
+            dy: 0.0,
+            pressure: 0.0,
+        }
+    }
+
+    /// Plays `events` to a new tracker, each a tenth of a second after the one before, and
+    /// returns the gestures they made.
+    fn play(events: &[Event], settings: &TouchSettings) -> Vec<Option<Gesture>> {
+        let mut tracker = TouchTracker::default();
+        let start = Instant::now();
+        (events.iter().enumerate())
+            .map(|(i, event)| {
+                let now = start + Duration::from_millis(100 * i as u64);
+                tracker.track(event, now, settings, &screen())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_tap_presses_and_releases() {
+        let gestures = play(&[down(0.5, 0.5), up(0.5, 0.5)], &gestures());
+        assert_eq!(
+            gestures,
+            [
+                Some(Gesture::Press(Point::new(400, 240))),
+                Some(Gesture::Release(Point::new(400, 240))),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_drag_sideways_swipes() {
+        let left = play(
+            &[down(0.5, 0.5), moved(0.4, 0.5), up(0.2, 0.5)],
+            &gestures(),
+        );
+        assert_eq!(left[2], Some(Gesture::Swipe(SwipeDirection::Left)));
+        let right = play(
+            &[down(0.5, 0.5), moved(0.6, 0.5), up(0.8, 0.55)],
+            &gestures(),
+        );
+        assert_eq!(right[2], Some(Gesture::Swipe(SwipeDirection::Right)));
+    }
+
+    #[test]
+    fn a_short_or_upright_drag_is_a_tap() {
+        let short = play(&[down(0.5, 0.5), up(0.6, 0.5)], &gestures());
+        assert_eq!(short[1], Some(Gesture::Release(Point::new(480, 240))));
+        let upright = play(&[down(0.5, 0.1), up(0.3, 0.9)], &gestures());
+        assert_eq!(upright[1], Some(Gesture::Release(Point::new(240, 432))));
+    }
+
+    #[test]
+    fn swipes_need_gestures() {
+        let swipe = play(&[down(0.5, 0.5), up(0.2, 0.5)], &TouchSettings::default());
+        assert_eq!(swipe[1], Some(Gesture::Release(Point::new(160, 240))));
+    }
+
+    #[test]
+    fn other_fingers_and_bounces_are_ignored() {
+        let mut tracker = TouchTracker::default();
+        let (settings, now) = (gestures(), Instant::now());
+        let other = Event::FingerDown {
+            timestamp: 0,
+            touch_id: 0,
+            finger_id: 2,
+            x: 0.1,
+            y: 0.1,
+            dx: 0.0,
+            dy: 0.0,
+            pressure: 1.0,
+        };
+        assert!(tracker
+            .track(&down(0.5, 0.5), now, &settings, &screen())
+            .is_some());
+        assert_eq!(tracker.track(&other, now, &settings, &screen()), None);
+        assert!(tracker
+            .track(&up(0.5, 0.5), now, &settings, &screen())
+            .is_some());
+        let soon = now + Duration::from_millis(100);
+        assert_eq!(
+            tracker.track(&down(0.5, 0.5), soon, &settings, &screen()),
+            None
+        );
+    }
+
+    #[test]
+    fn holding_still_long_presses_once() {
+        let mut tracker = TouchTracker::default();
+        let (settings, start) = (gestures(), Instant::now());
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        tracker.track(&down(0.5, 0.5), start, &settings, &screen());
+        // A wobble within the slop still counts as holding still.
+        tracker.track(&moved(0.52, 0.5), at(1.0), &settings, &screen());
+        assert_eq!(tracker.long_press(at(2.9), &settings), None);
+        assert_eq!(
+            tracker.long_press(at(3.0), &settings),
+            Some(Gesture::LongPress(Point::new(400, 240)))
+        );
+        assert_eq!(tracker.long_press(at(4.0), &settings), None);
+        assert_eq!(
+            tracker.track(&up(0.5, 0.5), at(5.0), &settings, &screen()),
+            None
+        );
+    }
+
+    #[test]
+    fn moving_away_or_lifting_cancels_a_long_press() {
+        let mut tracker = TouchTracker::default();
+        let (settings, start) = (gestures(), Instant::now());
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        tracker.track(&down(0.5, 0.5), start, &settings, &screen());
+        tracker.track(&moved(0.6, 0.5), at(1.0), &settings, &screen());
+        assert_eq!(tracker.long_press(at(3.0), &settings), None);
+
+        let mut tracker = TouchTracker::default();
+        tracker.track(&down(0.5, 0.5), start, &settings, &screen());
+        tracker.track(&up(0.5, 0.5), at(1.0), &settings, &screen());
+        assert_eq!(tracker.long_press(at(3.0), &settings), None);
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rocketbooth_libav::{
+    Decoder, Dictionary, Format, FormatContext, Frame, Packet, ProbeLimits, ReceiveResult,
+    AVERROR_INVALIDDATA,
+};
+
+use crate::VideoSource;
+
+/// An opened video source, decoding frames from its first video stream.
+pub struct VideoInput {
+    context: FormatContext,
+    decoder: Decoder,
+    packet: Packet,
+    stream_index: i32,
+    read_timeout: Option<Duration>,
+    draining: bool,
+}
+
+impl VideoInput {
+    pub fn open(video_source: &VideoSource) -> crate::Result<Self> {
+        let format = video_source
+            .format
+            .as_deref()
+            .map(Format::from_name)
+            .transpose()?;
+        let options = if video_source.options.is_empty() {
+            None
+        } else {
+            Some(Dictionary::from(&video_source.options))
+        };
+        let limits = ProbeLimits {
+            probesize: video_source.probesize,
+            analyze_duration: video_source.analyzeduration.map(Duration::from_micros),
+            timeout: Duration::try_from_secs_f64(video_source.open_timeout).ok(),
+        };
+        let mut context =
+            FormatContext::open_with_limits(&video_source.path, format, options, &limits)?;
+        context.find_stream_info()?;
+        let video_stream = context
+            .streams()
+            .find(|stream| stream.is_video())
+            .ok_or("No video stream")?;
+        let decoder = video_stream.create_decoder(video_source.video_codec.as_deref())?;
+        Ok(Self {
+            stream_index: video_stream.index(),
+            context,
+            decoder,
+            packet: Packet::new()?,
+            read_timeout: Duration::try_from_secs_f64(video_source.read_timeout).ok(),
+            draining: false,
+        })
+    }
+
+    /// Decodes the next frame, returning `None` once the input has ended.
+    pub fn next_frame(&mut self) -> crate::Result<Option<Frame>> {
+        let mut frame = Frame::new()?;
+        loop {
+            match self.decoder.receive(&mut frame)? {
+                ReceiveResult::Success => return Ok(Some(frame)),
+                ReceiveResult::Done => return Ok(None),
+                ReceiveResult::Pending if self.draining => return Ok(None),
+                ReceiveResult::Pending => {}
+            }
+            // A source that stops sending data without closing the connection counts as failed.
+            self.context.set_timeout(self.read_timeout);
+            if !self.context.read_into(&mut self.packet)? {
+                self.decoder.send_eof()?;
+                self.draining = true;
+                continue;
+            }
+            let result = if self.packet.stream_index() == self.stream_index {
+                self.decoder.send(&mut self.packet)
+            } else {
+                Ok(())
+            };
+            self.packet.decrement_ref_count();
+            match result {
+                Err(e) if e.code() == AVERROR_INVALIDDATA => {
+                    // A single corrupt packet (common with MJPEG webcams) shouldn't end the input.
+                    eprintln!("Skipping video packet: {e}");
+                }
+                result => result?,
+            }
+        }
+    }
+}
+
+/// Delay before reopening a failed video source. Doubles after each failed attempt, up to
+/// `RECONNECT_MAX_DELAY`.
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// The state of the connection to the video source, as seen by the thread reading from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// The source hasn't produced a frame yet.
+    Connecting,
+    /// Frames are arriving from the source.
+    Live,
+    /// The source stopped producing frames; it is being reopened.
+    Lost,
+}
+
+/// Reads frames from a video source on a background thread, reopening the source whenever it
+/// fails. Stops when dropped.
+pub struct VideoReader {
+    shared_frame: Arc<Mutex<Option<Frame>>>,
+    status: Arc<Mutex<ConnectionStatus>>,
+    device_lock: Arc<DeviceLock>,
+    control_bit: Arc<AtomicBool>,
+}
+
+impl VideoReader {
+    pub fn spawn(video_source: &VideoSource) -> Self {
+        let video_source = video_source.clone();
+        let shared_frame = Arc::new(Mutex::new(None));
+        let status = Arc::new(Mutex::new(ConnectionStatus::Connecting));
+        let device_lock = Arc::new(DeviceLock::default());
+        let control_bit = Arc::new(AtomicBool::new(true));
+        std::thread::spawn({
+            let shared_frame = Arc::clone(&shared_frame);
+            let status = Arc::clone(&status);
+            let device_lock = Arc::clone(&device_lock);
+            let control_bit = Arc::clone(&control_bit);
+            move || {
+                supervise_video_frames(
+                    &video_source,
+                    &shared_frame,
+                    &status,
+                    &device_lock,
+                    &control_bit,
+                )
+            }
+        });
+        Self {
+            shared_frame,
+            status,
+            device_lock,
+            control_bit,
+        }
+    }
+
+    /// Takes the most recent frame, if one has arrived since the last call.
+    pub fn take_frame(&self) -> Option<Frame> {
+        self.shared_frame.lock().ok()?.take()
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub fn device_lock(&self) -> Arc<DeviceLock> {
+        Arc::clone(&self.device_lock)
+    }
+}
+
+impl Drop for VideoReader {
+    fn drop(&mut self) {
+        self.control_bit.store(false, Ordering::Relaxed)
+    }
+}
+
+/// Coordinates access to a video device that can only be opened once, letting a still capture
+/// take the device away from the preview reader for a moment.
+#[derive(Default)]
+pub struct DeviceLock {
+    paused: AtomicBool,
+    device: Mutex<()>,
+}
+
+impl DeviceLock {
+    /// Asks the preview reader to close the device, and runs `f` once it has.
+    pub fn with_device<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.paused.store(true, Ordering::Relaxed);
+        let result = {
+            let _device = self.device.lock().unwrap();
+            f()
+        };
+        self.paused.store(false, Ordering::Relaxed);
+        result
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps reading from the video source until `control_bit` is cleared, reopening it with
+/// backoff whenever it fails or ends.
+fn supervise_video_frames(
+    video_source: &VideoSource,
+    shared_frame: &Mutex<Option<Frame>>,
+    status: &Mutex<ConnectionStatus>,
+    device_lock: &DeviceLock,
+    control_bit: &AtomicBool,
+) {
+    let mut delay = RECONNECT_MIN_DELAY;
+    while control_bit.load(Ordering::Relaxed) {
+        if device_lock.is_paused() {
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        let result = {
+            let _device = device_lock.device.lock().unwrap();
+            read_video_frames(video_source, shared_frame, status, device_lock, control_bit)
+        };
+        if !control_bit.load(Ordering::Relaxed) {
+            break;
+        }
+        match result {
+            // The device was handed over to a still capture; reopen it as soon as it's back.
+            Ok(()) if device_lock.is_paused() => continue,
+            Ok(()) => eprintln!("Video source {} ended", video_source.path),
+            Err(e) => eprintln!("Video source {} failed: {e}", video_source.path),
+        }
+        {
+            let mut status = status.lock().unwrap();
+            if *status == ConnectionStatus::Live {
+                *status = ConnectionStatus::Lost;
+                delay = RECONNECT_MIN_DELAY;
+            }
+        }
+        let retry_at = Instant::now() + delay;
+        while control_bit.load(Ordering::Relaxed) && Instant::now() < retry_at {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+}
+
+fn read_video_frames(
+    video_source: &VideoSource,
+    shared_frame: &Mutex<Option<Frame>>,
+    status: &Mutex<ConnectionStatus>,
+    device_lock: &DeviceLock,
+    control_bit: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = VideoInput::open(video_source)?;
+    while control_bit.load(Ordering::Relaxed) && !device_lock.is_paused() {
+        match input.next_frame()? {
+            Some(frame) => {
+                *shared_frame.lock().unwrap() = Some(frame);
+                *status.lock().unwrap() = ConnectionStatus::Live;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+    render::{BlendMode, Canvas, RenderTarget, Texture},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ReviewAction;
+
+/// A part of the screen, in fractions of its width and height, so it's in the same place whatever
+/// the resolution.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Area {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Area {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [self.x, self.y, self.width, self.height];
+        if values.iter().any(|value| !value.is_finite()) || self.width <= 0.0 || self.height <= 0.0
+        {
+            return Err(format!(
+                "Areas are given in fractions of the screen's size, eg 0.25, not {self:?}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The area on a screen of the given size.
+    pub fn rect(&self, screen_size: (u32, u32)) -> Rect {
+        let (width, height) = (screen_size.0 as f64, screen_size.1 as f64);
+        let (x, y) = ((self.x * width).round(), (self.y * height).round());
+        Rect::new(
+            x as i32,
+            y as i32,
+            (((self.x + self.width) * width).round() - x).max(1.0) as u32,
+            (((self.y + self.height) * height).round() - y).max(1.0) as u32,
+        )
+    }
+}
+
+/// The buttons the booth's screens can show, each named in the theme by `name`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ButtonId {
+    /// The review screen's buttons: `keep`, `retake_all`, `retake_shot` and `discard`.
+    Review(ReviewAction),
+    /// The button choosing this many copies: `copies_1`, `copies_2` and so on.
+    Copies(u32),
+    /// The operator menu's buttons: `resume`, `run_command` and `quit`.
+    Operator(OperatorChoice),
+}
+
+/// What the operator menu can do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OperatorChoice {
+    /// Close the menu and go back to idle.
+    Resume,
+    /// Run `controls.backspace_command`.
+    RunCommand,
+    /// Stop the booth.
+    Quit,
+}
+
+impl ButtonId {
+    /// The buttons with fixed names, ie all but the copies buttons.
+    pub const NAMED: [ButtonId; 7] = [
+        Self::Review(ReviewAction::Keep),
+        Self::Review(ReviewAction::RetakeAll),
+        Self::Review(ReviewAction::RetakeShot),
+        Self::Review(ReviewAction::Discard),
+        Self::Operator(OperatorChoice::Resume),
+        Self::Operator(OperatorChoice::RunCommand),
+        Self::Operator(OperatorChoice::Quit),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Review(ReviewAction::Keep) => "keep".into(),
+            Self::Review(ReviewAction::RetakeAll) => "retake_all".into(),
+            Self::Review(ReviewAction::RetakeShot) => "retake_shot".into(),
+            Self::Review(ReviewAction::Discard) => "discard".into(),
+            Self::Copies(copies) => format!("copies_{copies}"),
+            Self::Operator(OperatorChoice::Resume) => "resume".into(),
+            Self::Operator(OperatorChoice::RunCommand) => "run_command".into(),
+            Self::Operator(OperatorChoice::Quit) => "quit".into(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(button) = Self::NAMED.into_iter().find(|button| button.name() == name) {
+            return Some(button);
+        }
+        Some(Self::Copies(
+            name.strip_prefix("copies_")?
+                .parse()
+                .ok()
+                .filter(|copies| *copies > 0)?,
+        ))
+    }
+}
+
+/// How the theme shows one of the buttons. Anything left out is drawn the screen's own way.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ButtonStyle<T> {
+    /// Where the button is.
+    pub area: Option<Area>,
+    /// Drawn over the button's area.
+    pub image: Option<T>,
+    /// Drawn in place of `image` while the button is held down. Without one, the button is
+    /// darkened instead.
+    pub pressed: Option<T>,
+}
+
+/// A button on the current screen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Button {
+    pub id: ButtonId,
+    pub rect: Rect,
+}
+
+/// Places a screen's buttons where the theme puts them, or otherwise where the screen's own
+/// layout does.
+pub fn place_buttons<T>(
+    styles: &HashMap<String, ButtonStyle<T>>,
+    screen_size: (u32, u32),
+    defaults: impl IntoIterator<Item = (ButtonId, Rect)>,
+) -> Vec<Button> {
+    defaults
+        .into_iter()
+        .map(|(id, rect)| Button {
+            id,
+            rect: (styles.get(&id.name()))
+                .and_then(|style| style.area)
+                .map_or(rect, |area| area.rect(screen_size)),
+        })
+        .collect()
+}
+
+/// The button at `point`, if any. Where buttons overlap, the last one drawn wins.
+pub fn button_at(buttons: &[Button], point: Point) -> Option<ButtonId> {
+    buttons
+        .iter()
+        .rev()
+        .find(|button| button.rect.contains_point(point))
+        .map(|button| button.id)
+}
+
+/// Draws a button with the theme's images, or with `plain` if the theme has none for it.
+pub fn render_button<U>(
+    canvas: &mut Canvas<U>,
+    style: Option<&ButtonStyle<Texture>>,
+    button: Button,
+    pressed: bool,
+    plain: impl FnOnce(&mut Canvas<U>, Rect) -> crate::Result<()>,
+) -> crate::Result<()>
+where
+    U: RenderTarget,
+{
+    let image = style.and_then(|style| style.image.as_ref());
+    let pressed_image = style
+        .and_then(|style| style.pressed.as_ref())
+        .filter(|_| pressed);
+    match pressed_image.or(image) {
+        Some(image) => canvas.copy(image, None, button.rect)?,
+        None => plain(canvas, button.rect)?,
+    }
+    if pressed && pressed_image.is_none() {
+        let blend_mode = canvas.blend_mode();
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 96));
+        canvas.fill_rect(button.rect)?;
+        canvas.set_blend_mode(blend_mode);
+        canvas.set_draw_color(Color::BLACK);
+    }
+    Ok(())
+}